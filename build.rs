@@ -1,9 +1,10 @@
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::env;
 use std::fs;
 use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
 
-const MODEL_NAME: &str = "vosk-model-en-us-0.22";
 const MODEL_URL: &str = "https://alphacephei.com/vosk/models/vosk-model-en-us-0.22.zip";
 
 const VOSK_WINDOWS_URL: &str =
@@ -13,40 +14,279 @@ const VOSK_LINUX_URL: &str =
 const VOSK_MACOS_URL: &str =
     "https://github.com/alphacep/vosk-api/releases/download/v0.3.45/vosk-osx-0.3.45.zip";
 
+// Pinned digests in `sha256sum` format, keyed by archive file name
+const CHECKSUMS_FILE: &str = "assets.sha256";
+// Accepts a custom model archive (VOSK_MODEL_URL) that has no pinned digest
+const ALLOW_UNPINNED_ENV: &str = "VOSK_ALLOW_UNPINNED_MODEL";
+// Records the digest of each unpinned archive in CHECKSUMS_FILE instead of failing
+const PIN_ENV: &str = "VOSK_PIN_ASSETS";
+// Written last, after an asset has been fully extracted and validated
+const COMPLETE_MARKER: &str = ".complete";
+const REQUIRED_MODEL_FILES: &[&str] = &["am/final.mdl", "conf/model.conf", "ivector/final.ie"];
+
 type BuildResult<T> = Result<T, Box<dyn std::error::Error>>;
 
+struct Assets {
+    cache_dir: PathBuf,
+    checksums: HashMap<String, String>,
+    offline: bool,
+    model_url: String,
+    allow_unpinned_model: bool,
+    pin: bool,
+}
+
 fn main() {
     println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", CHECKSUMS_FILE);
     println!("cargo:rerun-if-env-changed=VOSK_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=VOSK_CACHE_DIR");
+    println!("cargo:rerun-if-env-changed=VOSK_OFFLINE");
+    println!("cargo:rerun-if-env-changed=VOSK_MODEL_URL");
+    println!("cargo:rerun-if-env-changed={}", ALLOW_UNPINNED_ENV);
+    println!("cargo:rerun-if-env-changed={}", PIN_ENV);
 
     let target_os = env::var("CARGO_CFG_TARGET_OS").unwrap_or_else(|_| String::from("unknown"));
 
+    let assets = match Assets::from_env() {
+        Ok(assets) => assets,
+        Err(e) => fail(&format!("Failed to prepare asset cache: {}", e)),
+    };
+
     // Setup Vosk library
-    if let Err(e) = setup_vosk_library(&target_os) {
+    if let Err(e) = setup_vosk_library(&assets, &target_os) {
+        if assets.offline {
+            fail(&format!("Vosk library unavailable in offline mode: {}", e));
+        }
         println!("cargo:warning=Failed to setup Vosk library: {}", e);
         println!("cargo:warning=Set VOSK_LIB_DIR to specify library location manually");
     }
 
     // Setup Vosk model
-    let models_dir = PathBuf::from("models");
-    let model_path = models_dir.join(MODEL_NAME);
+    let models_dir = assets.cache_dir.join("models");
+    let model_name = match assets.model_name() {
+        Ok(name) => name,
+        Err(e) => fail(&format!("Invalid VOSK_MODEL_URL: {}", e)),
+    };
+    let model_path = models_dir.join(model_name);
+    println!(
+        "cargo:rustc-env=VOSK_DEFAULT_MODEL_PATH={}",
+        model_path.display()
+    );
+
+    if is_complete(&model_path) && missing_model_files(&model_path).is_empty() {
+        println!("cargo:warning=✅ Using cached Vosk model");
+        return;
+    }
 
-    if model_path.exists() {
-        println!("cargo:warning=⚠️  Model corrupted, re-downloading...");
-        let _ = fs::remove_dir_all(&model_path);
+    if assets.offline {
+        fail(&format!(
+            "Vosk model {} is not in the cache at {} and VOSK_OFFLINE is set. \
+             Build once online or install it there manually.",
+            model_name,
+            models_dir.display()
+        ));
     }
 
     println!("cargo:warning=Downloading Vosk model...");
-    if let Err(e) = download_and_extract_model(&models_dir) {
+    if let Err(e) = download_and_extract_model(&assets, &models_dir) {
         println!("cargo:warning=Failed to download model: {}", e);
-        println!("cargo:warning=Download manually from: {}", MODEL_URL);
+        println!("cargo:warning=Download manually from: {}", assets.model_url);
         println!("cargo:warning=Extract to: {}", models_dir.display());
     } else {
         println!("cargo:warning=✅ Vosk model ready");
     }
 }
 
-fn setup_vosk_library(target_os: &str) -> BuildResult<()> {
+impl Assets {
+    fn from_env() -> BuildResult<Self> {
+        let cache_dir = match env::var_os("VOSK_CACHE_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => default_cache_dir()?,
+        };
+        fs::create_dir_all(&cache_dir)?;
+
+        let model_url = env::var("VOSK_MODEL_URL").unwrap_or_else(|_| MODEL_URL.to_string());
+        let allow_unpinned_model = env_flag(ALLOW_UNPINNED_ENV);
+        if allow_unpinned_model && model_url == MODEL_URL {
+            return Err(format!(
+                "{} only applies to a custom VOSK_MODEL_URL; the default model is always verified",
+                ALLOW_UNPINNED_ENV
+            )
+            .into());
+        }
+
+        Ok(Self {
+            cache_dir,
+            checksums: load_checksums(Path::new(CHECKSUMS_FILE))?,
+            offline: env_flag("VOSK_OFFLINE"),
+            model_url,
+            allow_unpinned_model,
+            pin: env_flag(PIN_ENV),
+        })
+    }
+
+    /// Directory the model archive extracts to: its file name without `.zip`.
+    fn model_name(&self) -> BuildResult<&str> {
+        self.model_url
+            .rsplit('/')
+            .next()
+            .and_then(|file_name| file_name.strip_suffix(".zip"))
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| format!("{} is not a .zip archive", self.model_url).into())
+    }
+
+    /// Downloads `url` into the cache (resuming a partial download) and checks its digest.
+    fn fetch(&self, url: &str) -> BuildResult<PathBuf> {
+        let file_name = url.rsplit('/').next().ok_or("Invalid URL")?;
+        let downloads = self.cache_dir.join("downloads");
+        fs::create_dir_all(&downloads)?;
+        let archive = downloads.join(file_name);
+
+        if !archive.exists() {
+            download_file(url, &archive)?;
+        }
+
+        // A bad or unpinned archive fails the build instead of falling back to manual setup
+        if let Err(e) = self.verify(&archive, file_name, url) {
+            let _ = fs::remove_file(&archive);
+            fail(&e.to_string());
+        }
+
+        Ok(archive)
+    }
+
+    fn verify(&self, path: &Path, file_name: &str, url: &str) -> BuildResult<()> {
+        let actual = sha256_file(path)?;
+
+        match self.checksums.get(file_name) {
+            Some(expected) if expected.eq_ignore_ascii_case(&actual) => Ok(()),
+            Some(expected) => Err(format!(
+                "Checksum mismatch for {}: expected {}, got {}",
+                file_name, expected, actual
+            )
+            .into()),
+            None if self.pin => {
+                append_checksum(Path::new(CHECKSUMS_FILE), file_name, &actual)?;
+                println!(
+                    "cargo:warning=Pinned {} (SHA-256 {}) in {}; check it against the release \
+                     and commit it",
+                    file_name, actual, CHECKSUMS_FILE
+                );
+                Ok(())
+            }
+            None if self.allow_unpinned_model && url == self.model_url => {
+                println!(
+                    "cargo:warning=Accepting unpinned {} (SHA-256 {}) because {} is set",
+                    file_name, actual, ALLOW_UNPINNED_ENV
+                );
+                Ok(())
+            }
+            None => Err(format!(
+                "No pinned SHA-256 for {} (got {}); add `{}  {}` to {}",
+                file_name, actual, actual, file_name, CHECKSUMS_FILE
+            )
+            .into()),
+        }
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    env::var(name)
+        .map(|v| v == "1" || v.eq_ignore_ascii_case("true"))
+        .unwrap_or(false)
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(1);
+}
+
+fn default_cache_dir() -> BuildResult<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+
+    base.map(|dir| dir.join("discord-voice-bot"))
+        .ok_or_else(|| "Could not determine a cache directory, set VOSK_CACHE_DIR".into())
+}
+
+fn load_checksums(path: &Path) -> BuildResult<HashMap<String, String>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+
+    let mut checksums = HashMap::new();
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some(digest), Some(file_name)) => {
+                checksums.insert(file_name.trim_start_matches('*').to_string(), digest.into());
+            }
+            _ => return Err(format!("Malformed line in {}: {}", path.display(), line).into()),
+        }
+    }
+
+    Ok(checksums)
+}
+
+fn append_checksum(path: &Path, file_name: &str, digest: &str) -> BuildResult<()> {
+    let mut file = fs::OpenOptions::new().append(true).open(path)?;
+    writeln!(file, "{}  {}", digest, file_name)?;
+    Ok(())
+}
+
+fn sha256_file(path: &Path) -> BuildResult<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 65536];
+
+    loop {
+        let bytes_read = file.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..bytes_read]);
+    }
+
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+fn is_complete(dir: &Path) -> bool {
+    dir.join(COMPLETE_MARKER).is_file()
+}
+
+fn missing_model_files(model_path: &Path) -> Vec<String> {
+    let mut missing: Vec<String> = REQUIRED_MODEL_FILES
+        .iter()
+        .filter(|file| !model_path.join(file).is_file())
+        .map(|file| file.to_string())
+        .collect();
+
+    // Models ship either a static HCLG graph or a dynamic HCLr + Gr pair
+    let graph = model_path.join("graph");
+    let static_graph = graph.join("HCLG.fst").is_file();
+    let dynamic_graph = graph.join("HCLr.fst").is_file() && graph.join("Gr.fst").is_file();
+    if !static_graph && !dynamic_graph {
+        missing.push(String::from(
+            "graph/HCLG.fst (or graph/HCLr.fst + graph/Gr.fst)",
+        ));
+    }
+
+    missing
+}
+
+fn setup_vosk_library(assets: &Assets, target_os: &str) -> BuildResult<()> {
     // Use custom library path if provided
     if let Ok(vosk_lib_dir) = env::var("VOSK_LIB_DIR") {
         println!("cargo:warning=Using custom VOSK_LIB_DIR: {}", vosk_lib_dir);
@@ -61,28 +301,41 @@ fn setup_vosk_library(target_os: &str) -> BuildResult<()> {
         _ => return Err(format!("Unsupported platform: {}", target_os).into()),
     };
 
-    let lib_dir = PathBuf::from("lib").join(target_os);
+    let lib_dir = assets.cache_dir.join("lib").join(target_os);
     let lib_file = lib_dir.join(lib_name);
 
     // Skip if library already exists
-    if lib_file.exists() && lib_file.metadata()?.len() > 0 {
-        println!("cargo:warning=✅ Using existing Vosk library");
+    if is_complete(&lib_dir) && lib_file.is_file() && lib_file.metadata()?.len() > 0 {
+        println!("cargo:warning=✅ Using cached Vosk library");
         println!("cargo:rustc-link-search=native={}", lib_dir.display());
         copy_runtime_dependencies(&lib_dir)?;
         return Ok(());
     }
 
+    if assets.offline {
+        return Err(format!(
+            "{} is not in the cache at {} and VOSK_OFFLINE is set",
+            lib_name,
+            lib_dir.display()
+        )
+        .into());
+    }
+
     println!(
         "cargo:warning=Downloading Vosk library for {}...",
         target_os
     );
-    fs::create_dir_all(&lib_dir)?;
-
-    let zip_path = lib_dir.join("vosk.zip");
-    download_file(url, &zip_path)?;
+    let zip_path = assets.fetch(url)?;
 
     println!("cargo:warning=Extracting Vosk library...");
-    extract_library_files(&zip_path, &lib_dir)?;
+    let staging = staging_dir(&lib_dir)?;
+    extract_library_files(&zip_path, &staging)?;
+    if !staging.join(lib_name).is_file() {
+        fs::remove_dir_all(&staging)?;
+        return Err(format!("{} not found in {}", lib_name, url).into());
+    }
+    fs::write(staging.join(COMPLETE_MARKER), "")?;
+    replace_dir(&staging, &lib_dir)?;
     fs::remove_file(&zip_path)?;
 
     println!("cargo:rustc-link-search=native={}", lib_dir.display());
@@ -114,31 +367,89 @@ fn copy_runtime_dependencies(lib_dir: &Path) -> BuildResult<()> {
     Ok(())
 }
 
-fn download_and_extract_model(models_dir: &Path) -> BuildResult<()> {
+fn download_and_extract_model(assets: &Assets, models_dir: &Path) -> BuildResult<()> {
     fs::create_dir_all(models_dir)?;
-    let zip_path = models_dir.join("model.zip");
+    let model_name = assets.model_name()?;
+    let zip_path = assets.fetch(&assets.model_url)?;
+
+    // Extract next to the final location and only move it into place once it is whole
+    let staging = staging_dir(&models_dir.join(model_name))?;
+    extract_zip(&zip_path, &staging)?;
+
+    let extracted = staging.join(model_name);
+    let missing = missing_model_files(&extracted);
+    if !missing.is_empty() {
+        fs::remove_dir_all(&staging)?;
+        return Err(format!(
+            "Model archive is incomplete, missing: {}",
+            missing.join(", ")
+        )
+        .into());
+    }
 
-    download_file(MODEL_URL, &zip_path)?;
-    extract_zip(&zip_path, models_dir)?;
+    fs::write(extracted.join(COMPLETE_MARKER), "")?;
+    replace_dir(&extracted, &models_dir.join(model_name))?;
+    fs::remove_dir_all(&staging)?;
     fs::remove_file(&zip_path)?;
 
     Ok(())
 }
 
+fn staging_dir(final_dir: &Path) -> BuildResult<PathBuf> {
+    let name = final_dir.file_name().ok_or("Invalid directory")?;
+    let staging = final_dir.with_file_name(format!("{}.partial", name.to_string_lossy()));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    Ok(staging)
+}
+
+fn replace_dir(from: &Path, to: &Path) -> BuildResult<()> {
+    if to.exists() {
+        fs::remove_dir_all(to)?;
+    }
+    fs::rename(from, to)?;
+    Ok(())
+}
+
 fn download_file(url: &str, dest_path: &Path) -> BuildResult<()> {
-    let response = ureq::get(url)
-        .timeout(std::time::Duration::from_secs(600))
-        .call()?;
+    // Keep partial data under a separate name so an interrupted download can be resumed
+    let partial_path = PathBuf::from(format!("{}.part", dest_path.display()));
+    let resume_from = fs::metadata(&partial_path).map(|m| m.len()).unwrap_or(0);
+
+    let mut request = ureq::get(url).timeout(std::time::Duration::from_secs(600));
+    if resume_from > 0 {
+        println!("cargo:warning=Resuming download at {} bytes", resume_from);
+        request = request.set("Range", &format!("bytes={}-", resume_from));
+    }
+
+    let response = match request.call() {
+        Ok(response) => response,
+        // The partial file already holds the whole body
+        Err(ureq::Error::Status(416, _)) if resume_from > 0 => {
+            fs::rename(&partial_path, dest_path)?;
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+
+    let resumed = resume_from > 0 && response.status() == 206;
+    let mut downloaded = if resumed { resume_from } else { 0 };
 
     let total_size = response
         .header("content-length")
         .and_then(|s| s.parse::<u64>().ok())
+        .map(|len| len + downloaded)
         .unwrap_or(0);
 
     let mut reader = response.into_reader();
-    let mut file = fs::File::create(dest_path)?;
+    let mut file = if resumed {
+        fs::OpenOptions::new().append(true).open(&partial_path)?
+    } else {
+        fs::File::create(&partial_path)?
+    };
     let mut buffer = [0u8; 65536];
-    let mut downloaded = 0u64;
     let mut last_progress = 0;
 
     loop {
@@ -150,8 +461,7 @@ fn download_file(url: &str, dest_path: &Path) -> BuildResult<()> {
         file.write_all(&buffer[..bytes_read])?;
         downloaded += bytes_read as u64;
 
-        if total_size > 0 {
-            let progress = (downloaded * 100) / total_size;
+        if let Some(progress) = (downloaded * 100).checked_div(total_size) {
             if progress >= last_progress + 20 {
                 println!("cargo:warning=Progress: {}%", progress);
                 last_progress = progress;
@@ -159,6 +469,16 @@ fn download_file(url: &str, dest_path: &Path) -> BuildResult<()> {
         }
     }
 
+    file.sync_all()?;
+    if total_size > 0 && downloaded != total_size {
+        return Err(format!(
+            "Download of {} ended early ({} of {} bytes), rebuild to resume",
+            url, downloaded, total_size
+        )
+        .into());
+    }
+
+    fs::rename(&partial_path, dest_path)?;
     Ok(())
 }
 
@@ -0,0 +1,141 @@
+use crate::metrics::METRICS;
+use crate::pipeline::{AudioFormat, AudioStage};
+
+/// Level speech is normalized to: -20 dBFS RMS.
+const TARGET_RMS: f32 = 3277.0;
+/// Blocks quieter than this (-50 dBFS RMS) are pauses or background; the gain is held so
+/// silence isn't boosted into noise.
+const GATE_RMS: f32 = 100.0;
+const MIN_GAIN: f32 = 0.25;
+const MAX_GAIN: f32 = 10.0;
+/// Peaks are limited to -1 dBFS.
+const PEAK_LIMIT: f32 = 29_204.0;
+/// Time constants for turning the gain down (a loud speaker) and back up (a quiet one).
+const ATTACK_SECS: f32 = 0.02;
+const RELEASE_SECS: f32 = 0.5;
+
+/// Automatic gain control and limiter for one speaker's audio.
+///
+/// The gain tracks the level of each block of speech towards a target, falling quickly when the
+/// speaker gets louder and rising slowly when they get quieter. A peak limiter then lowers the
+/// gain for any block that would still clip.
+pub struct Agc {
+    gain: f32,
+    /// Interleaved samples per second of audio.
+    sample_rate: f32,
+}
+
+impl Agc {
+    pub fn new(format: AudioFormat) -> Self {
+        Self {
+            gain: 1.0,
+            sample_rate: format.samples(1.0) as f32,
+        }
+    }
+}
+
+impl AudioStage for Agc {
+    /// Normalizes a block of samples.
+    fn process(&mut self, mut samples: Vec<i16>) -> Vec<i16> {
+        if samples.is_empty() {
+            return samples;
+        }
+
+        let (sum_squares, peak) = samples.iter().fold((0.0, 0.0), |(sum, peak), sample| {
+            let sample = f32::from(*sample);
+            (sum + sample * sample, f32::max(peak, sample.abs()))
+        });
+        let rms = (sum_squares / samples.len() as f32).sqrt();
+
+        let previous = self.gain;
+        if rms >= GATE_RMS {
+            let desired = (TARGET_RMS / rms).clamp(MIN_GAIN, MAX_GAIN);
+            let block_secs = samples.len() as f32 / self.sample_rate;
+            let time_constant = if desired < self.gain {
+                ATTACK_SECS
+            } else {
+                RELEASE_SECS
+            };
+            self.gain += (desired - self.gain) * (1.0 - (-block_secs / time_constant).exp());
+        }
+        let gain = if peak * self.gain > PEAK_LIMIT {
+            PEAK_LIMIT / peak
+        } else {
+            self.gain
+        };
+        METRICS.agc_gain.observe(f64::from(gain));
+
+        // Ramp from the previous gain so changes don't click
+        let step = (gain - previous) / samples.len() as f32;
+        for (i, sample) in samples.iter_mut().enumerate() {
+            let scaled = f32::from(*sample) * (previous + step * (i + 1) as f32);
+            *sample = scaled.round().clamp(-PEAK_LIMIT, PEAK_LIMIT) as i16;
+        }
+        // Carry on from the limited gain, so the next ramp starts where this one ended
+        self.gain = gain;
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 20 ms of a 400 Hz tone, a whole number of periods.
+    fn tone(amplitude: f32) -> Vec<i16> {
+        (0..320)
+            .map(|i| (amplitude * (i as f32 * std::f32::consts::TAU / 40.0).sin()).round() as i16)
+            .collect()
+    }
+
+    fn rms(samples: &[i16]) -> f32 {
+        let sum: f32 = samples.iter().map(|s| f32::from(*s).powi(2)).sum();
+        (sum / samples.len() as f32).sqrt()
+    }
+
+    /// Feeds `secs` of the tone and returns the last block.
+    fn run(agc: &mut Agc, amplitude: f32, secs: usize) -> Vec<i16> {
+        let mut output = Vec::new();
+        for _ in 0..secs * 50 {
+            output = agc.process(tone(amplitude));
+        }
+        output
+    }
+
+    #[test]
+    fn converges_towards_the_target() {
+        let mut agc = Agc::new(AudioFormat::RECOGNIZER);
+        let quiet = run(&mut agc, 600.0, 5);
+        assert!((rms(&quiet) - TARGET_RMS).abs() < TARGET_RMS * 0.05);
+
+        // Falls quickly for a louder speaker
+        let loud = run(&mut agc, 12_000.0, 1);
+        assert!((rms(&loud) - TARGET_RMS).abs() < TARGET_RMS * 0.05);
+    }
+
+    #[test]
+    fn respects_the_maximum_gain() {
+        let mut agc = Agc::new(AudioFormat::RECOGNIZER);
+        let output = run(&mut agc, 150.0, 10);
+        assert!(agc.gain <= MAX_GAIN && agc.gain > MAX_GAIN * 0.95);
+        assert!(rms(&output) <= rms(&tone(150.0)) * MAX_GAIN + 1.0);
+    }
+
+    #[test]
+    fn does_not_amplify_silence() {
+        let mut agc = Agc::new(AudioFormat::RECOGNIZER);
+        assert_eq!(run(&mut agc, 0.0, 1), tone(0.0));
+        // Background below the gate keeps the gain it had
+        assert_eq!(run(&mut agc, 50.0, 5), tone(50.0));
+        assert_eq!(agc.gain, 1.0);
+    }
+
+    #[test]
+    fn limits_peaks() {
+        let mut agc = Agc::new(AudioFormat::RECOGNIZER);
+        let output = agc.process(tone(32_000.0));
+        assert!(output
+            .iter()
+            .all(|s| f32::from(s.unsigned_abs()) <= PEAK_LIMIT));
+    }
+}
@@ -0,0 +1,60 @@
+use crate::pipeline;
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Discord bot that transcribes voice channels with Vosk"
+)]
+pub struct Cli {
+    /// Directory models are installed in
+    #[arg(long, global = true, env = "VOSK_MODEL_DIR")]
+    pub model_dir: Option<PathBuf>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Run the bot (the default)
+    Run {
+        /// Model name in the model directory, or a path to a model
+        #[arg(long, env = "VOSK_MODEL")]
+        model: Option<String>,
+    },
+    /// Manage installed speech models
+    #[command(subcommand)]
+    Models(ModelsCommand),
+    /// Measure the CPU cost of each audio processing stage for one speaker
+    Bench {
+        /// Comma-separated stages, in order
+        #[arg(long, env = "AUDIO_PIPELINE", default_value = pipeline::DEFAULT_STAGES)]
+        pipeline: String,
+        /// Seconds of audio to process
+        #[arg(long, default_value_t = 60)]
+        seconds: u64,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ModelsCommand {
+    /// List installed models
+    List,
+    /// Install a model by name from the Vosk model repository, or from a local zip
+    Install {
+        /// Model name (e.g. vosk-model-small-en-us-0.15) or path to a .zip archive
+        source: String,
+    },
+    /// Check that a model has all required files
+    Verify {
+        /// Model name or path (defaults to the default model)
+        model: Option<String>,
+    },
+    /// Delete an installed model
+    Remove {
+        /// Model name
+        name: String,
+    },
+}
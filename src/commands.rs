@@ -0,0 +1,361 @@
+use serenity::model::id::UserId;
+
+/// Prefix used in servers that haven't set their own, and in direct messages.
+pub const DEFAULT_PREFIX: &str = "!";
+const MAX_PREFIX_LEN: usize = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CommandKind {
+    Join,
+    Leave,
+    Status,
+    Search,
+    History,
+    PostProcess,
+    Profanity,
+    Translate,
+    Permissions,
+    Prefix,
+    Tts,
+    Voice,
+    Mode,
+    Denoise,
+    Endpoint,
+    Help,
+}
+
+/// A command in the registry; `!help` is generated from these.
+pub struct Command {
+    pub kind: CommandKind,
+    pub name: &'static str,
+    pub aliases: &'static [&'static str],
+    /// Arguments, shown after the command name in usage lines.
+    pub args: &'static str,
+    pub description: &'static str,
+}
+
+pub static COMMANDS: &[Command] = &[
+    Command {
+        kind: CommandKind::Join,
+        name: "join",
+        aliases: &["j"],
+        args: "[#channel | name | id]",
+        description: "Join your voice channel, or the given one, and start transcribing",
+    },
+    Command {
+        kind: CommandKind::Leave,
+        name: "leave",
+        aliases: &["stop", "disconnect"],
+        args: "",
+        description: "Leave the voice channel",
+    },
+    Command {
+        kind: CommandKind::Status,
+        name: "status",
+        aliases: &[],
+        args: "",
+        description: "Connection state, channel, uptime, speakers, model and latency",
+    },
+    Command {
+        kind: CommandKind::Search,
+        name: "search",
+        aliases: &["find"],
+        args: "<terms> [@user] [since:YYYY-MM-DD]",
+        description: "Search this server's transcript history",
+    },
+    Command {
+        kind: CommandKind::History,
+        name: "history",
+        aliases: &[],
+        args: "[opt-out | opt-in | retention <days|default>]",
+        description: "Show or change history retention and whether your utterances are stored",
+    },
+    Command {
+        kind: CommandKind::PostProcess,
+        name: "postprocess",
+        aliases: &["pp"],
+        args: "[stage... | none]",
+        description: "Show or set the text post-processing chain",
+    },
+    Command {
+        kind: CommandKind::Profanity,
+        name: "profanity",
+        aliases: &[],
+        args: "[mode <off|mask|remove|captions-only> | block <word...> | allow <word...>]",
+        description: "Show or change the profanity filter",
+    },
+    Command {
+        kind: CommandKind::Translate,
+        name: "translate",
+        aliases: &[],
+        args: "[language... | off]",
+        description: "Show or set the languages captions are translated into",
+    },
+    Command {
+        kind: CommandKind::Permissions,
+        name: "permissions",
+        aliases: &["perms"],
+        args:
+            "[<command> allow <@role|@user...> | <command> deny <@role|@user...> | <command> reset]",
+        description: "Show or change which roles and users may run each command",
+    },
+    Command {
+        kind: CommandKind::Prefix,
+        name: "prefix",
+        aliases: &[],
+        args: "[<prefix> | default]",
+        description: "Show or change this server's command prefix",
+    },
+    Command {
+        kind: CommandKind::Mode,
+        name: "mode",
+        aliases: &[],
+        args: "[per-speaker | mixed]",
+        description: "Show or set whether each speaker gets a recognizer or everyone shares one",
+    },
+    Command {
+        kind: CommandKind::Denoise,
+        name: "denoise",
+        aliases: &[],
+        args: "[on | off]",
+        description: "Show or toggle background noise suppression before recognition",
+    },
+    Command {
+        kind: CommandKind::Endpoint,
+        name: "endpoint",
+        aliases: &[],
+        args: "[<min-secs> <max-secs> | default]",
+        description: "Show or set how much silence ends an utterance",
+    },
+    Command {
+        kind: CommandKind::Tts,
+        name: "tts",
+        aliases: &["readaloud"],
+        args: "[#channel | off]",
+        description: "Read a text channel's messages aloud in the voice channel this session",
+    },
+    Command {
+        kind: CommandKind::Voice,
+        name: "voice",
+        aliases: &[],
+        args: "[voice | default]",
+        description: "Show or pick the voice your messages are read aloud with",
+    },
+    Command {
+        kind: CommandKind::Help,
+        name: "help",
+        aliases: &["commands"],
+        args: "[command]",
+        description: "List commands, or show how to use one",
+    },
+];
+
+/// Looks up a command by name or alias, ignoring case.
+pub fn find(name: &str) -> Option<&'static Command> {
+    let name = name.to_lowercase();
+    COMMANDS
+        .iter()
+        .find(|command| command.name == name || command.aliases.contains(&name.as_str()))
+}
+
+/// A parsed command message.
+pub struct Invocation {
+    pub command: &'static Command,
+    /// Prefix to show in replies; the guild's prefix even when the bot was mentioned.
+    pub prefix: String,
+    pub args: Vec<String>,
+}
+
+impl Invocation {
+    /// Parses `content` addressed to the bot with `prefix` or a mention of `bot_id`.
+    ///
+    /// A bare mention is treated as `help`. Unknown commands are ignored, since other bots
+    /// commonly share the `!` prefix.
+    pub fn parse(content: &str, prefix: &str, bot_id: UserId) -> Option<Self> {
+        let content = content.trim_start();
+        let mentions = [format!("<@{}>", bot_id), format!("<@!{}>", bot_id)];
+        let mention_rest = mentions
+            .iter()
+            .find_map(|mention| content.strip_prefix(mention.as_str()));
+        let rest = match mention_rest {
+            Some(rest) if rest.is_empty() || rest.starts_with(char::is_whitespace) => {
+                let rest = rest.trim_start();
+                // Also accept "@bot !join"
+                rest.strip_prefix(prefix).unwrap_or(rest)
+            }
+            Some(_) => return None,
+            None => content.strip_prefix(prefix)?,
+        };
+
+        let mut words = split_args(rest).into_iter();
+        let command = match words.next() {
+            Some(name) => find(&name)?,
+            None if mention_rest.is_some() => find("help")?,
+            None => return None,
+        };
+        Some(Self {
+            command,
+            prefix: prefix.to_string(),
+            args: words.collect(),
+        })
+    }
+
+    pub fn args(&self) -> Vec<&str> {
+        self.args.iter().map(String::as_str).collect()
+    }
+
+    pub fn usage(&self) -> String {
+        format!("Usage: {}", usage_line(self.command, &self.prefix))
+    }
+}
+
+/// Splits arguments on whitespace; double quotes group words, as in `"general voice"`.
+fn split_args(input: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_arg = false;
+    for c in input.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_arg = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+    args
+}
+
+fn usage_line(command: &Command, prefix: &str) -> String {
+    if command.args.is_empty() {
+        format!("`{}{}`", prefix, command.name)
+    } else {
+        format!("`{}{} {}`", prefix, command.name, command.args)
+    }
+}
+
+/// `!help` lists every command; `!help <command>` shows its usage and aliases.
+pub fn help(prefix: &str, topic: Option<&str>) -> String {
+    let Some(topic) = topic else {
+        let mut reply = String::from("**Commands**");
+        for command in COMMANDS {
+            reply.push_str(&format!(
+                "\n`{}{}` — {}",
+                prefix, command.name, command.description
+            ));
+        }
+        reply.push_str(&format!(
+            "\n\nUse `{}help <command>` for details. Mentioning the bot works in place of the prefix.",
+            prefix
+        ));
+        return reply;
+    };
+
+    let Some(command) = find(topic.trim_start_matches(prefix)) else {
+        return format!(
+            "❌ Unknown command `{}`. Use `{}help` for a list",
+            topic.replace('`', "'"),
+            prefix
+        );
+    };
+    let mut reply = format!("{}\n{}", usage_line(command, prefix), command.description);
+    if !command.aliases.is_empty() {
+        let aliases: Vec<String> = command
+            .aliases
+            .iter()
+            .map(|alias| format!("`{}{}`", prefix, alias))
+            .collect();
+        reply.push_str(&format!("\nAliases: {}", aliases.join(", ")));
+    }
+    reply
+}
+
+/// Checks a prefix chosen with `!prefix`.
+pub fn validate_prefix(prefix: &str) -> Result<(), String> {
+    if prefix.chars().count() > MAX_PREFIX_LEN {
+        Err(format!(
+            "❌ Prefixes can be at most {} characters",
+            MAX_PREFIX_LEN
+        ))
+    } else if prefix.is_empty()
+        || prefix
+            .chars()
+            .any(|c| c.is_whitespace() || c == '`' || c == '"')
+    {
+        Err(String::from(
+            "❌ Prefixes can't contain spaces, quotes or backticks",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOT: UserId = UserId::new(42);
+
+    fn parse(content: &str, prefix: &str) -> Option<(CommandKind, Vec<String>)> {
+        Invocation::parse(content, prefix, BOT)
+            .map(|invocation| (invocation.command.kind, invocation.args))
+    }
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(
+            split_args(r#"  "general voice"  lobby "" x"#),
+            ["general voice", "lobby", "", "x"]
+        );
+        assert_eq!(split_args(r#"a"b c"d"#), ["ab cd"]);
+        assert!(split_args("   ").is_empty());
+    }
+
+    #[test]
+    fn parses_commands_and_aliases() {
+        assert_eq!(
+            parse("!join \"general voice\"", "!"),
+            Some((CommandKind::Join, vec![String::from("general voice")]))
+        );
+        assert_eq!(parse("!STOP", "!"), Some((CommandKind::Leave, vec![])));
+    }
+
+    #[test]
+    fn uses_the_custom_prefix() {
+        assert_eq!(parse("?>status", "?>"), Some((CommandKind::Status, vec![])));
+        assert_eq!(parse("!status", "?>"), None);
+
+        let invocation = Invocation::parse("<@42> ?>join", "?>", BOT).unwrap();
+        assert_eq!(invocation.command.kind, CommandKind::Join);
+        assert_eq!(invocation.prefix, "?>");
+        assert_eq!(invocation.usage(), "Usage: `?>join [#channel | name | id]`");
+    }
+
+    #[test]
+    fn ignores_unknown_commands() {
+        assert_eq!(parse("!play song", "!"), None);
+        assert_eq!(parse("<@42> play", "!"), None);
+        assert_eq!(parse("hello !join", "!"), None);
+    }
+
+    #[test]
+    fn handles_empty_input() {
+        assert_eq!(parse("", "!"), None);
+        assert_eq!(parse("!", "!"), None);
+        assert_eq!(parse("<@!42>", "!"), Some((CommandKind::Help, vec![])));
+        assert_eq!(parse("<@42>x", "!"), None);
+    }
+}
@@ -1,14 +1,159 @@
+use crate::pipeline::{self, StageKind};
+use crate::tts;
+use serenity::prelude::TypeMapKey;
 use std::env;
 use std::error::Error;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_HTTP_ADDR: &str = "127.0.0.1:9090";
+const DEFAULT_LOG_FILTER: &str = "info";
+const DEFAULT_TRANSCRIPT_DIR: &str = "transcripts";
+const DEFAULT_MAX_RECOGNIZERS: usize = 16;
+const DEFAULT_RECOGNIZER_POOL_SIZE: usize = 4;
+const DEFAULT_SPEAKER_IDLE_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_MAX_UTTERANCE_SECS: u64 = 30;
+const DEFAULT_GUILD_SETTINGS_PATH: &str = "guild_settings.json";
+const DEFAULT_HISTORY_DB: &str = "history.sqlite3";
+const DEFAULT_HISTORY_RETENTION_DAYS: u32 = 90;
+const DEFAULT_TTS_CMD: &[&str] = &["espeak-ng", "--stdout", "-v", "{voice}"];
+const DEFAULT_TTS_VOICE: &str = "en";
+
+#[derive(Clone, Copy)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
 
 pub struct Config {
     pub discord_token: String,
+    pub http_addr: SocketAddr,
+    pub log_filter: String,
+    pub log_format: LogFormat,
+    pub transcript_dir: PathBuf,
+    pub max_recognizers: usize,
+    pub recognizer_pool_size: usize,
+    pub speaker_idle_timeout: Duration,
+    pub max_utterance: Duration,
+    pub guild_settings_path: PathBuf,
+    /// Local punctuation model command, split on whitespace.
+    pub punctuation_model_cmd: Option<Vec<String>>,
+    /// Base URL of a LibreTranslate-compatible server; translation is off when unset.
+    pub translate_url: Option<String>,
+    pub translate_api_key: Option<String>,
+    /// Token WebSocket clients must present; the live stream is off when unset.
+    pub stream_token: Option<String>,
+    pub history_db: PathBuf,
+    pub history_retention_days: u32,
+    /// Text-to-speech command for read-aloud, split on whitespace.
+    pub tts_cmd: Vec<String>,
+    /// Voice used for members who haven't picked one.
+    pub tts_voice: String,
+    /// Audio stages between each speaker's decoded voice and their recognizer, in order.
+    pub audio_pipeline: Vec<StageKind>,
 }
 
 impl Config {
     pub fn from_env() -> Result<Self, Box<dyn Error>> {
-        dotenv::dotenv().ok();
         let discord_token = env::var("DISCORD_TOKEN").map_err(|_| "DISCORD_TOKEN not set")?;
-        Ok(Self { discord_token })
+        let http_addr = env::var("HTTP_ADDR")
+            .unwrap_or_else(|_| DEFAULT_HTTP_ADDR.to_string())
+            .parse()
+            .map_err(|e| format!("Invalid HTTP_ADDR: {}", e))?;
+        let log_filter = env::var("LOG_FILTER")
+            .or_else(|_| env::var("RUST_LOG"))
+            .unwrap_or_else(|_| DEFAULT_LOG_FILTER.to_string());
+        let log_format = match env::var("LOG_FORMAT").as_deref() {
+            Ok("json") => LogFormat::Json,
+            Ok("text") | Err(_) => LogFormat::Text,
+            Ok(other) => return Err(format!("Invalid LOG_FORMAT: {}", other).into()),
+        };
+        let transcript_dir = env::var("TRANSCRIPT_DIR")
+            .unwrap_or_else(|_| DEFAULT_TRANSCRIPT_DIR.to_string())
+            .into();
+        let max_recognizers = parse_var("MAX_RECOGNIZERS", DEFAULT_MAX_RECOGNIZERS)?;
+        let recognizer_pool_size = parse_var("RECOGNIZER_POOL_SIZE", DEFAULT_RECOGNIZER_POOL_SIZE)?;
+        let speaker_idle_timeout = Duration::from_secs(parse_var(
+            "SPEAKER_IDLE_TIMEOUT_SECS",
+            DEFAULT_SPEAKER_IDLE_TIMEOUT_SECS,
+        )?);
+        let max_utterance_secs = parse_var("MAX_UTTERANCE_SECS", DEFAULT_MAX_UTTERANCE_SECS)?;
+        if max_utterance_secs < 2 {
+            return Err("MAX_UTTERANCE_SECS must be at least 2".into());
+        }
+        let max_utterance = Duration::from_secs(max_utterance_secs);
+        let guild_settings_path = env::var("GUILD_SETTINGS_PATH")
+            .unwrap_or_else(|_| DEFAULT_GUILD_SETTINGS_PATH.to_string())
+            .into();
+        let punctuation_model_cmd = env::var("PUNCTUATION_MODEL_CMD")
+            .ok()
+            .map(|cmd| cmd.split_whitespace().map(String::from).collect::<Vec<_>>())
+            .filter(|cmd| !cmd.is_empty());
+        let translate_url = env::var("TRANSLATE_URL").ok().filter(|url| !url.is_empty());
+        let translate_api_key = env::var("TRANSLATE_API_KEY")
+            .ok()
+            .filter(|key| !key.is_empty());
+        let stream_token = env::var("STREAM_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty());
+        let history_db = env::var("HISTORY_DB")
+            .unwrap_or_else(|_| DEFAULT_HISTORY_DB.to_string())
+            .into();
+        let history_retention_days =
+            parse_var("HISTORY_RETENTION_DAYS", DEFAULT_HISTORY_RETENTION_DAYS)?;
+        let tts_cmd = env::var("TTS_CMD")
+            .ok()
+            .map(|cmd| cmd.split_whitespace().map(String::from).collect::<Vec<_>>())
+            .filter(|cmd| !cmd.is_empty())
+            .unwrap_or_else(|| DEFAULT_TTS_CMD.iter().map(|arg| arg.to_string()).collect());
+        let tts_voice = env::var("TTS_VOICE").unwrap_or_else(|_| DEFAULT_TTS_VOICE.to_string());
+        if !tts::is_valid_voice(&tts_voice) {
+            return Err(format!("Invalid TTS_VOICE: {}", tts_voice).into());
+        }
+        let audio_pipeline = pipeline::parse_stages(
+            &env::var("AUDIO_PIPELINE").unwrap_or_else(|_| pipeline::DEFAULT_STAGES.to_string()),
+        )
+        .map_err(|e| format!("Invalid AUDIO_PIPELINE: {}", e))?;
+        Ok(Self {
+            discord_token,
+            http_addr,
+            log_filter,
+            log_format,
+            transcript_dir,
+            max_recognizers,
+            recognizer_pool_size,
+            speaker_idle_timeout,
+            max_utterance,
+            guild_settings_path,
+            punctuation_model_cmd,
+            translate_url,
+            translate_api_key,
+            stream_token,
+            history_db,
+            history_retention_days,
+            tts_cmd,
+            tts_voice,
+            audio_pipeline,
+        })
+    }
+}
+
+fn parse_var<T>(name: &str, default: T) -> Result<T, Box<dyn Error>>
+where
+    T: std::str::FromStr,
+    T::Err: std::fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| format!("Invalid {}: {}", name, e).into()),
+        Err(_) => Ok(default),
     }
 }
+
+pub struct ConfigKey;
+impl TypeMapKey for ConfigKey {
+    type Value = Arc<Config>;
+}
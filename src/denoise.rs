@@ -0,0 +1,83 @@
+use crate::metrics::METRICS;
+use crate::pipeline::AudioStage;
+use nnnoiseless::DenoiseState;
+use std::time::Instant;
+
+/// RNNoise works on 10 ms frames of 48 kHz mono audio.
+const FRAME_SIZE: usize = DenoiseState::FRAME_SIZE;
+
+/// Suppresses steady background noise (fans, keyboards, game audio) in one speaker's 48 kHz
+/// stream with RNNoise.
+///
+/// Each channel keeps its own state. Audio is processed in whole 10 ms frames; a partial frame
+/// waits for the next call, so output can lag input by up to 10 ms.
+pub struct Denoiser {
+    channels: Vec<Channel>,
+}
+
+struct Channel {
+    state: Box<DenoiseState<'static>>,
+    pending: Vec<f32>,
+}
+
+impl Denoiser {
+    pub fn new(channels: usize) -> Self {
+        Self {
+            channels: (0..channels).map(|_| Channel::new()).collect(),
+        }
+    }
+}
+
+impl AudioStage for Denoiser {
+    fn process(&mut self, samples: Vec<i16>) -> Vec<i16> {
+        let started = Instant::now();
+        let count = self.channels.len();
+        let denoised: Vec<Vec<f32>> = self
+            .channels
+            .iter_mut()
+            .enumerate()
+            .map(|(i, channel)| channel.process(samples.iter().skip(i).step_by(count)))
+            .collect();
+
+        let frames = denoised.first().map_or(0, Vec::len);
+        let output = (0..frames)
+            .flat_map(|frame| denoised.iter().map(move |channel| to_i16(channel[frame])))
+            .collect();
+        METRICS
+            .denoise_seconds
+            .observe(started.elapsed().as_secs_f64());
+        output
+    }
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            state: DenoiseState::new(),
+            pending: Vec::with_capacity(FRAME_SIZE * 2),
+        }
+    }
+
+    fn process<'a>(&mut self, samples: impl Iterator<Item = &'a i16>) -> Vec<f32> {
+        // RNNoise expects samples at i16 scale, not normalized to [-1, 1]
+        self.pending
+            .extend(samples.map(|sample| f32::from(*sample)));
+
+        let mut output = vec![0.0; self.pending.len() / FRAME_SIZE * FRAME_SIZE];
+        for (input, output) in self
+            .pending
+            .chunks_exact(FRAME_SIZE)
+            .zip(output.chunks_exact_mut(FRAME_SIZE))
+        {
+            self.state.process_frame(output, input);
+        }
+        self.pending.drain(..output.len());
+        output
+    }
+}
+
+fn to_i16(sample: f32) -> i16 {
+    sample
+        .round()
+        .clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16
+}
@@ -1,140 +1,1537 @@
-use crate::transcription::Receiver;
+use crate::commands::{self, CommandKind, Invocation};
+use crate::config::ConfigKey;
+use crate::guild_settings::{EndpointSettings, GuildSettingsKey, ProfanityMode, TranscriptionMode};
+use crate::health::HealthKey;
+use crate::history::{self, HistoryKey, HistorySink, SearchQuery};
+use crate::live_stream::{LiveStreamKey, LiveStreamSink};
+use crate::permissions::{self, Caller};
+use crate::pipeline::StageKind;
+use crate::postprocess::{self, ProfanityFilter};
+use crate::recognizer_pool::{RecognizerPool, RecognizerPoolKey};
+use crate::session::{format_duration, Session, SessionsKey};
+use crate::sink::{self, ArchiveSink, ChannelSink, SessionContext, TranscriptSink, WebhookSink};
+use crate::transcription::{Receiver, ReceiverOptions};
+use crate::translation::{SessionTranslator, TranslationServiceKey};
+use crate::tts::{self, TtsReader, Utterance};
+use crate::vosk_model;
 use serenity::async_trait;
+use serenity::builder::{CreateAllowedMentions, CreateMessage};
 use serenity::client::{Context, EventHandler};
+use serenity::gateway::{ConnectionStage, ShardStageUpdateEvent};
 use serenity::model::gateway::Ready;
-use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::id::{ChannelId, GuildId, UserId};
 use serenity::model::prelude::*;
-use serenity::prelude::*;
+use serenity::model::Permissions;
 use songbird::Event;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use vosk::Model;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, info_span, warn};
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(1);
 
 pub struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _ctx: Context, _ready: Ready) {}
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!(user = %ready.user.name, guilds = ready.guilds.len(), "Connected to Discord");
+        set_gateway_connected(&ctx, true).await;
+    }
+
+    async fn resume(&self, ctx: Context, _event: ResumedEvent) {
+        set_gateway_connected(&ctx, true).await;
+    }
+
+    async fn shard_stage_update(&self, ctx: Context, event: ShardStageUpdateEvent) {
+        info!(shard = %event.shard_id, stage = %event.new, "Shard stage changed");
+        set_gateway_connected(&ctx, event.new == ConnectionStage::Connected).await;
+    }
+
+    async fn voice_state_update(&self, ctx: Context, _old: Option<VoiceState>, new: VoiceState) {
+        // Drop the session if the bot was disconnected from voice by someone else
+        if new.user_id != ctx.cache.current_user().id || new.channel_id.is_some() {
+            return;
+        }
+
+        if let Some(guild_id) = new.guild_id {
+            let session = ctx
+                .data
+                .read()
+                .await
+                .get::<SessionsKey>()
+                .and_then(|sessions| sessions.remove(guild_id));
+            if let Some(session) = session {
+                info!(guild_id = guild_id.get(), "Voice session ended");
+                session.close().await;
+            }
+        }
+    }
 
     async fn message(&self, ctx: Context, msg: Message) {
         if msg.author.bot {
             return;
         }
 
-        match msg.content.as_str() {
-            "!join" => {
-                let channel_id_opt = if let Some(guild_id) = msg.guild_id {
-                    let guild = ctx.cache.guild(guild_id);
-                    if let Some(guild_ref) = guild {
-                        guild_ref
-                            .voice_states
-                            .get(&msg.author.id)
-                            .and_then(|vs| vs.channel_id)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                };
+        let prefix = match msg.guild_id {
+            Some(guild_id) => guild_prefix(&ctx, guild_id).await,
+            None => String::from(commands::DEFAULT_PREFIX),
+        };
+        let bot_id = ctx.cache.current_user().id;
+        let Some(invocation) = Invocation::parse(&msg.content, &prefix, bot_id) else {
+            if let Some(guild_id) = msg.guild_id {
+                read_aloud(&ctx, &msg, guild_id).await;
+            }
+            return;
+        };
+
+        let reply = match msg.guild_id {
+            Some(guild_id) => run_command(&ctx, &msg, guild_id, &invocation).await,
+            None if invocation.command.kind == CommandKind::Help => {
+                commands::help(&invocation.prefix, invocation.args().first().copied())
+            }
+            None => String::from("❌ This command must be used in a server!"),
+        };
+        if let Err(e) = reply_quietly(&ctx, &msg, reply).await {
+            warn!(command = invocation.command.name, "Failed to reply: {}", e);
+        }
+    }
+}
+
+async fn guild_prefix(ctx: &Context, guild_id: GuildId) -> String {
+    ctx.data
+        .read()
+        .await
+        .get::<GuildSettingsKey>()
+        .and_then(|settings| settings.get(guild_id).prefix)
+        .unwrap_or_else(|| String::from(commands::DEFAULT_PREFIX))
+}
+
+/// Checks the caller's permissions and runs a command, returning the reply.
+async fn run_command(
+    ctx: &Context,
+    msg: &Message,
+    guild_id: GuildId,
+    invocation: &Invocation,
+) -> String {
+    if let Err(denied) = check_permissions(ctx, msg, guild_id, invocation).await {
+        return denied;
+    }
+
+    match invocation.command.kind {
+        CommandKind::Join => join(ctx, msg, guild_id, invocation).await,
+        CommandKind::Leave => leave(ctx, guild_id).await,
+        CommandKind::Status => status_report(ctx, guild_id).await,
+        CommandKind::Search => search_history(ctx, guild_id, invocation).await,
+        CommandKind::History => configure_history(ctx, guild_id, msg.author.id, invocation).await,
+        CommandKind::PostProcess => configure_post_processing(ctx, guild_id, invocation).await,
+        CommandKind::Profanity => configure_profanity(ctx, guild_id, invocation).await,
+        CommandKind::Translate => configure_translation(ctx, guild_id, invocation).await,
+        CommandKind::Permissions => configure_permissions(ctx, guild_id, invocation).await,
+        CommandKind::Prefix => configure_prefix(ctx, guild_id, invocation).await,
+        CommandKind::Mode => configure_mode(ctx, guild_id, invocation).await,
+        CommandKind::Denoise => configure_denoise(ctx, guild_id, invocation).await,
+        CommandKind::Endpoint => configure_endpoint(ctx, guild_id, invocation).await,
+        CommandKind::Tts => configure_tts(ctx, guild_id, msg.author.id, invocation).await,
+        CommandKind::Voice => configure_voice(ctx, guild_id, msg.author.id, invocation).await,
+        CommandKind::Help => commands::help(&invocation.prefix, invocation.args().first().copied()),
+    }
+}
+
+/// Joins the caller's voice channel, or the channel named in the arguments.
+async fn join(ctx: &Context, msg: &Message, guild_id: GuildId, invocation: &Invocation) -> String {
+    let channel_id = if invocation.args.is_empty() {
+        let channel_id = ctx.cache.guild(guild_id).and_then(|guild| {
+            guild
+                .voice_states
+                .get(&msg.author.id)
+                .and_then(|vs| vs.channel_id)
+        });
+        match channel_id {
+            Some(channel_id) => channel_id,
+            None => return String::from("❌ You must be in a voice channel first!"),
+        }
+    } else {
+        match resolve_voice_channel(ctx, guild_id, &invocation.args.join(" ")) {
+            Ok(channel_id) => channel_id,
+            Err(e) => return e,
+        }
+    };
+    if let Err(e) = check_can_join(ctx, guild_id, channel_id).await {
+        return e;
+    }
+
+    let pool = match ctx.data.read().await.get::<RecognizerPoolKey>() {
+        Some(pool) => pool.clone(),
+        None => return String::from("❌ Bot not properly initialized"),
+    };
+
+    match join_voice_channel(ctx, guild_id, channel_id, msg.channel_id, pool).await {
+        Ok(_) => format!("✅ Joined <#{}>!", channel_id),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                channel_id = channel_id.get(),
+                "Failed to join: {}",
+                e
+            );
+            format!("❌ Failed to join: {}", e)
+        }
+    }
+}
+
+/// Links a text channel whose messages are read aloud for the rest of the session.
+async fn configure_tts(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    invocation: &Invocation,
+) -> String {
+    // Clone out of the data map so the lock isn't held across the awaits below
+    let (sessions, config) = {
+        let data = ctx.data.read().await;
+        match (data.get::<SessionsKey>(), data.get::<ConfigKey>()) {
+            (Some(sessions), Some(config)) => (sessions.clone(), config.clone()),
+            _ => return String::from("❌ Bot not properly initialized"),
+        }
+    };
+    let not_joined = format!(
+        "❌ The bot isn't in a voice channel; use `{}join` first",
+        invocation.prefix
+    );
+
+    let channel_id = match invocation.args().as_slice() {
+        [] => {
+            return match sessions.tts(guild_id) {
+                Some(tts) => format!(
+                    "Reading <#{}> aloud\n{}",
+                    tts.channel_id(),
+                    invocation.usage()
+                ),
+                None => format!("Read-aloud is off\n{}", invocation.usage()),
+            }
+        }
+        ["off"] => {
+            return if sessions.set_tts(guild_id, None) {
+                String::from("✅ Read-aloud stopped")
+            } else {
+                not_joined
+            }
+        }
+        [channel] => match parse_channel_mention(channel) {
+            Some(channel_id) => channel_id,
+            None => return invocation.usage(),
+        },
+        _ => return invocation.usage(),
+    };
+
+    let member = match guild_id.member(ctx, user_id).await {
+        Ok(member) => member,
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to look up the caller: {}", e
+            );
+            return String::from("❌ Couldn't check your permissions, try again");
+        }
+    };
+    let problem = match ctx.cache.guild(guild_id) {
+        None => Some(String::from("❌ This server isn't cached yet, try again")),
+        Some(guild) => match guild.channels.get(&channel_id) {
+            // Reading a channel aloud shouldn't reveal messages the caller can't see
+            Some(channel) if matches!(channel.kind, ChannelType::Text | ChannelType::Voice) => {
+                let missing = (Permissions::VIEW_CHANNEL | Permissions::READ_MESSAGE_HISTORY)
+                    - guild.user_permissions_in(channel, &member);
+                (!missing.is_empty()).then(|| {
+                    format!(
+                        "❌ You need the {} permission in <#{}>",
+                        missing.get_permission_names().join(", "),
+                        channel_id
+                    )
+                })
+            }
+            _ => Some(format!(
+                "❌ <#{}> is not a text channel in this server",
+                channel_id
+            )),
+        },
+    };
+    if let Some(problem) = problem {
+        return problem;
+    }
+
+    let call = match songbird::get(ctx).await {
+        Some(manager) => manager.get(guild_id),
+        None => None,
+    };
+    let Some(call) = call else {
+        return not_joined;
+    };
+    let span = info_span!(
+        "tts",
+        guild_id = guild_id.get(),
+        channel_id = channel_id.get()
+    );
+    let reader = TtsReader::spawn(config.tts_cmd.clone(), call, channel_id, span);
+    if sessions.set_tts(guild_id, Some(reader)) {
+        format!(
+            "✅ Reading <#{}> aloud until the bot leaves; `{}voice` picks your voice",
+            channel_id, invocation.prefix
+        )
+    } else {
+        not_joined
+    }
+}
+
+/// Queues a message from the session's read-aloud channel, if it was posted there.
+async fn read_aloud(ctx: &Context, msg: &Message, guild_id: GuildId) {
+    let data = ctx.data.read().await;
+    let Some(tts) = data
+        .get::<SessionsKey>()
+        .and_then(|sessions| sessions.tts(guild_id))
+        .filter(|tts| tts.channel_id() == msg.channel_id)
+    else {
+        return;
+    };
+    let Some(config) = data.get::<ConfigKey>() else {
+        return;
+    };
+
+    let voice = data
+        .get::<GuildSettingsKey>()
+        .and_then(|settings| {
+            settings
+                .get(guild_id)
+                .tts_voices
+                .get(&msg.author.id.get())
+                .cloned()
+        })
+        .unwrap_or_else(|| config.tts_voice.clone());
+    let author_name = msg
+        .member
+        .as_ref()
+        .and_then(|member| member.nick.clone())
+        .unwrap_or_else(|| msg.author.display_name().to_string());
+    let queued = tts.say(Utterance {
+        author: msg.author.id,
+        author_name,
+        voice,
+        text: msg.content_safe(&ctx.cache),
+    });
+    if !queued {
+        warn!(
+            guild_id = guild_id.get(),
+            "Read-aloud queue is full, dropping message"
+        );
+    }
+}
+
+/// Shows or sets the voice the caller's messages are read aloud with.
+async fn configure_voice(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    invocation: &Invocation,
+) -> String {
+    let data = ctx.data.read().await;
+    let (Some(settings), Some(config)) = (data.get::<GuildSettingsKey>(), data.get::<ConfigKey>())
+    else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    let voice = match invocation.args().as_slice() {
+        [] => {
+            let voice = settings
+                .get(guild_id)
+                .tts_voices
+                .get(&user_id.get())
+                .cloned();
+            return format!(
+                "Your read-aloud voice: `{}`\n{}",
+                voice.unwrap_or_else(|| format!("{} (default)", config.tts_voice)),
+                invocation.usage()
+            );
+        }
+        ["default"] => None,
+        [voice] if tts::is_valid_voice(voice) => Some(voice.to_string()),
+        [voice] => {
+            return format!(
+                "❌ `{}` is not a voice name; use names like `en-us` or `en+f3`",
+                voice.replace('`', "'")
+            )
+        }
+        _ => return invocation.usage(),
+    };
+
+    let shown = voice.clone().unwrap_or_else(|| config.tts_voice.clone());
+    let result = settings.update(guild_id, |guild| match voice {
+        Some(voice) => {
+            guild.tts_voices.insert(user_id.get(), voice);
+        }
+        None => {
+            guild.tts_voices.remove(&user_id.get());
+        }
+    });
+    match result {
+        Ok(()) => format!("✅ Your messages will be read aloud with `{}`", shown),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+fn parse_channel_mention(arg: &str) -> Option<ChannelId> {
+    arg.strip_prefix("<#")?
+        .strip_suffix('>')?
+        .parse::<u64>()
+        .ok()
+        .filter(|id| *id != 0)
+        .map(ChannelId::new)
+}
+
+/// Finds the channel a `join` argument refers to: a channel mention, an ID or a name.
+fn resolve_voice_channel(
+    ctx: &Context,
+    guild_id: GuildId,
+    target: &str,
+) -> Result<ChannelId, String> {
+    let guild = ctx
+        .cache
+        .guild(guild_id)
+        .ok_or("❌ This server isn't cached yet, try again")?;
+
+    let id = target
+        .strip_prefix("<#")
+        .and_then(|id| id.strip_suffix('>'))
+        .unwrap_or(target);
+    if let Some(channel_id) = id.parse::<u64>().ok().filter(|id| *id != 0) {
+        let channel_id = ChannelId::new(channel_id);
+        return if guild.channels.contains_key(&channel_id) {
+            Ok(channel_id)
+        } else {
+            Err(format!(
+                "❌ No channel with ID `{}` in this server",
+                channel_id
+            ))
+        };
+    }
 
-                if let Some(guild_id) = msg.guild_id {
-                    if let Some(channel_id) = channel_id_opt {
-                        let data = ctx.data.read().await;
-
-                        let model = match data.get::<VoskModelKey>() {
-                            Some(m) => m.clone(),
-                            None => {
-                                let _ = msg
-                                    .reply(&ctx.http, "❌ Bot not properly initialized")
-                                    .await;
-                                return;
-                            }
-                        };
-
-                        drop(data);
-
-                        let join_result =
-                            join_voice_channel(&ctx, guild_id, channel_id, model).await;
-                        match join_result {
-                            Ok(_) => {
-                                let _ = msg.reply(&ctx.http, "✅ Joined your voice channel!").await;
-                            }
-                            Err(e) => {
-                                let error_msg = format!("❌ Failed to join: {}", e);
-                                let _ = msg.reply(&ctx.http, error_msg).await;
-                            }
-                        }
-                    } else {
-                        let _ = msg
-                            .reply(&ctx.http, "❌ You must be in a voice channel first!")
-                            .await;
+    let name = target.trim_start_matches('#');
+    let mut matching = guild.channels.values().filter(|channel| {
+        matches!(channel.kind, ChannelType::Voice | ChannelType::Stage)
+            && channel.name.eq_ignore_ascii_case(name)
+    });
+    let name = name.replace('`', "'");
+    match (matching.next(), matching.next()) {
+        (Some(channel), None) => Ok(channel.id),
+        (Some(_), Some(_)) => Err(format!(
+            "❌ Several voice channels are named `{}`; use a channel mention or ID",
+            name
+        )),
+        (None, _) => Err(format!("❌ No voice channel named `{}`", name)),
+    }
+}
+
+/// Checks that the bot can connect and speak in a voice channel and that there is room for it.
+async fn check_can_join(
+    ctx: &Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<(), String> {
+    let bot_id = ctx.cache.current_user().id;
+    let member = guild_id.member(ctx, bot_id).await.map_err(|e| {
+        warn!(
+            guild_id = guild_id.get(),
+            "Failed to look up the bot member: {}", e
+        );
+        String::from("❌ Couldn't check the bot's permissions, try again")
+    })?;
+
+    let guild = ctx
+        .cache
+        .guild(guild_id)
+        .ok_or("❌ This server isn't cached yet, try again")?;
+    let channel = guild
+        .channels
+        .get(&channel_id)
+        .ok_or("❌ That channel no longer exists")?;
+    match channel.kind {
+        ChannelType::Voice => {}
+        ChannelType::Stage => {
+            return Err(format!(
+                "❌ <#{}> is a stage channel; only voice channels can be transcribed",
+                channel_id
+            ))
+        }
+        _ => return Err(format!("❌ <#{}> is not a voice channel", channel_id)),
+    }
+
+    let permissions = guild.user_permissions_in(channel, &member);
+    let missing =
+        (Permissions::VIEW_CHANNEL | Permissions::CONNECT | Permissions::SPEAK) - permissions;
+    if !missing.is_empty() {
+        return Err(format!(
+            "❌ The bot needs the {} permission in <#{}>",
+            missing.get_permission_names().join(", "),
+            channel_id
+        ));
+    }
+
+    // Move Members lets the bot join a channel past its user limit
+    if let Some(limit) = channel.user_limit.filter(|limit| *limit > 0) {
+        let occupants = guild
+            .voice_states
+            .values()
+            .filter(|state| state.channel_id == Some(channel_id) && state.user_id != bot_id)
+            .count();
+        if occupants >= limit as usize && !permissions.move_members() {
+            return Err(format!(
+                "❌ <#{}> is full ({}/{} members)",
+                channel_id, occupants, limit
+            ));
+        }
+    }
+    Ok(())
+}
+
+async fn leave(ctx: &Context, guild_id: GuildId) -> String {
+    let manager = songbird::get(ctx).await.expect("Songbird not initialized");
+
+    let session = ctx
+        .data
+        .read()
+        .await
+        .get::<SessionsKey>()
+        .and_then(|sessions| sessions.remove(guild_id));
+    if let Some(session) = session {
+        session.close().await;
+    }
+
+    match manager.remove(guild_id).await {
+        Ok(_) => {
+            info!(guild_id = guild_id.get(), "Left voice channel");
+            String::from("👋 Left the voice channel!")
+        }
+        Err(e) => {
+            warn!(guild_id = guild_id.get(), "Failed to leave: {:?}", e);
+            String::from("❌ Failed to leave voice channel!")
+        }
+    }
+}
+
+/// Replies without pinging anyone mentioned in the reply.
+async fn reply_quietly(
+    ctx: &Context,
+    msg: &Message,
+    content: impl Into<String>,
+) -> serenity::Result<Message> {
+    let message = CreateMessage::new()
+        .content(content)
+        .reference_message(msg)
+        .allowed_mentions(CreateAllowedMentions::new());
+    msg.channel_id.send_message(&ctx.http, message).await
+}
+
+/// Runs the guild's permission rules for an invoked command.
+async fn check_permissions(
+    ctx: &Context,
+    msg: &Message,
+    guild_id: GuildId,
+    invocation: &Invocation,
+) -> Result<(), String> {
+    let caller = match Caller::fetch(ctx, guild_id, msg.author.id).await {
+        Ok(caller) => caller,
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to check permissions: {}", e
+            );
+            return Err(String::from(
+                "❌ Couldn't check your permissions, try again",
+            ));
+        }
+    };
+
+    let data = ctx.data.read().await;
+    let settings = data
+        .get::<GuildSettingsKey>()
+        .map(|settings| settings.get(guild_id))
+        .unwrap_or_default();
+    let session_channel = data
+        .get::<SessionsKey>()
+        .and_then(|sessions| sessions.status(guild_id))
+        .map(|status| status.channel_id);
+
+    permissions::check(&settings, invocation, &caller, session_channel)
+}
+
+/// Shows or edits which roles and users may run each command.
+async fn configure_permissions(
+    ctx: &Context,
+    guild_id: GuildId,
+    invocation: &Invocation,
+) -> String {
+    let args = invocation.args();
+    let data = ctx.data.read().await;
+    let Some(settings) = data.get::<GuildSettingsKey>() else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    let Some((command, rest)) = args.split_first() else {
+        let restricted: Vec<String> = settings
+            .get(guild_id)
+            .command_permissions
+            .iter()
+            .filter(|(_, access)| access.is_restricted())
+            .map(|(command, access)| {
+                let holders: Vec<String> = access
+                    .roles
+                    .iter()
+                    .map(|id| format!("<@&{}>", id))
+                    .chain(access.users.iter().map(|id| format!("<@{}>", id)))
+                    .collect();
+                format!("`{}{}`: {}", invocation.prefix, command, holders.join(", "))
+            })
+            .collect();
+        return if restricted.is_empty() {
+            format!(
+                "No commands are restricted to roles or users; default Discord permission checks apply\n{}",
+                invocation.usage()
+            )
+        } else {
+            format!("Restricted commands:\n{}", restricted.join("\n"))
+        };
+    };
+
+    let Some(command) = commands::find(command.trim_start_matches(invocation.prefix.as_str()))
+    else {
+        let names: Vec<&str> = commands::COMMANDS
+            .iter()
+            .map(|command| command.name)
+            .collect();
+        return format!(
+            "❌ Unknown command `{}`. Commands: {}",
+            command.replace('`', "'"),
+            names.join(", ")
+        );
+    };
+    let command = command.name.to_string();
+
+    let mut roles = Vec::new();
+    let mut users = Vec::new();
+    for mention in rest.iter().skip(1) {
+        if let Some(role_id) = parse_role_mention(mention) {
+            roles.push(role_id);
+        } else if let Some(user_id) = parse_user_mention(mention) {
+            users.push(user_id.get());
+        } else {
+            return format!("❌ `{}` is not a role or user mention", mention);
+        }
+    }
+
+    let result = match rest.first() {
+        Some(&"allow") if !roles.is_empty() || !users.is_empty() => {
+            settings.update(guild_id, |guild| {
+                let access = guild
+                    .command_permissions
+                    .entry(command.clone())
+                    .or_default();
+                for role in roles {
+                    if !access.roles.contains(&role) {
+                        access.roles.push(role);
                     }
+                }
+                for user in users {
+                    if !access.users.contains(&user) {
+                        access.users.push(user);
+                    }
+                }
+            })
+        }
+        Some(&"deny") if !roles.is_empty() || !users.is_empty() => {
+            settings.update(guild_id, |guild| {
+                if let Some(access) = guild.command_permissions.get_mut(&command) {
+                    access.roles.retain(|role| !roles.contains(role));
+                    access.users.retain(|user| !users.contains(user));
+                }
+                guild
+                    .command_permissions
+                    .retain(|_, access| access.is_restricted());
+            })
+        }
+        Some(&"reset") if rest.len() == 1 => settings.update(guild_id, |guild| {
+            guild.command_permissions.remove(&command);
+        }),
+        _ => return invocation.usage(),
+    };
+
+    match result {
+        Ok(()) => {
+            let access = settings
+                .get(guild_id)
+                .command_permissions
+                .get(&command)
+                .cloned()
+                .unwrap_or_default();
+            if access.is_restricted() {
+                format!(
+                    "✅ `{}{}` is limited to {} role(s) and {} user(s); administrators can always use it",
+                    invocation.prefix,
+                    command,
+                    access.roles.len(),
+                    access.users.len()
+                )
+            } else {
+                format!(
+                    "✅ `{}{}` uses the default permission checks",
+                    invocation.prefix, command
+                )
+            }
+        }
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+/// Shows or changes the guild's command prefix; mentioning the bot always works too.
+async fn configure_prefix(ctx: &Context, guild_id: GuildId, invocation: &Invocation) -> String {
+    let data = ctx.data.read().await;
+    let Some(settings) = data.get::<GuildSettingsKey>() else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    let prefix = match invocation.args().as_slice() {
+        [] => return format!("Prefix: `{}`\n{}", invocation.prefix, invocation.usage()),
+        ["default"] => None,
+        [prefix] => match commands::validate_prefix(prefix) {
+            Ok(()) => Some(prefix.to_string()),
+            Err(e) => return e,
+        },
+        _ => return invocation.usage(),
+    };
+
+    let shown = prefix
+        .clone()
+        .unwrap_or_else(|| String::from(commands::DEFAULT_PREFIX));
+    match settings.update(guild_id, |guild| guild.prefix = prefix) {
+        Ok(()) => format!("✅ Prefix set to `{}`", shown),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+fn parse_role_mention(arg: &str) -> Option<u64> {
+    arg.strip_prefix("<@&")?
+        .strip_suffix('>')?
+        .parse::<u64>()
+        .ok()
+        .filter(|id| *id != 0)
+}
+
+const SEARCH_RESULTS: usize = 5;
+const MAX_REPLY_LEN: usize = 1900;
+
+/// Searches the guild's transcript history, showing each match with the lines around it.
+async fn search_history(ctx: &Context, guild_id: GuildId, invocation: &Invocation) -> String {
+    let (history, settings, default_retention_days) = {
+        let data = ctx.data.read().await;
+        let (Some(history), Some(settings), Some(config)) = (
+            data.get::<HistoryKey>(),
+            data.get::<GuildSettingsKey>(),
+            data.get::<ConfigKey>(),
+        ) else {
+            return String::from("❌ Bot not properly initialized");
+        };
+        (
+            history.clone(),
+            settings.get(guild_id),
+            config.history_retention_days,
+        )
+    };
+
+    let retention_days = settings
+        .history_retention_days
+        .unwrap_or(default_retention_days);
+    let mut query = SearchQuery {
+        terms: Vec::new(),
+        user_id: None,
+        since: history::retention_cutoff(retention_days),
+        limit: SEARCH_RESULTS,
+    };
+
+    for arg in invocation.args() {
+        if let Some(date) = arg.strip_prefix("since:") {
+            match history::parse_date(date) {
+                Some(since) => query.since = query.since.max(since),
+                None => return format!("❌ Invalid date `{}`. {}", date, invocation.usage()),
+            }
+        } else if let Some(user_id) = parse_user_mention(arg) {
+            query.user_id = Some(user_id);
+        } else {
+            query.terms.push(arg.to_string());
+        }
+    }
+    if query.terms.is_empty() {
+        return invocation.usage();
+    }
+
+    let terms = query.terms.join(" ");
+    let hits = match history.search(guild_id, query).await {
+        Ok(hits) => hits,
+        Err(e) => {
+            warn!(guild_id = guild_id.get(), "History search failed: {}", e);
+            return String::from("❌ Search failed");
+        }
+    };
+    if hits.is_empty() {
+        return format!("No results for `{}`", terms.replace('`', "'"));
+    }
+
+    // History may hold unfiltered text (captions-only mode), so mask it like the captions
+    let profanity = &settings.profanity;
+    let filter = match profanity.mode {
+        ProfanityMode::Off => None,
+        ProfanityMode::Remove => Some(true),
+        ProfanityMode::Mask | ProfanityMode::CaptionsOnly => Some(false),
+    }
+    .map(|remove| ProfanityFilter::new(remove, &profanity.blocked, &profanity.allowed));
+
+    let mut reply = format!("🔎 Results for `{}`", terms.replace('`', "'"));
+    for hit in hits {
+        let offset = format_duration(hit.line.offset());
+        let mut entry = format!(
+            "\n\n<t:{}:f> · {} into the session",
+            hit.line.started_at / 1000,
+            offset
+        );
+        for (line, is_match) in [
+            (&hit.before, false),
+            (&Some(hit.line), true),
+            (&hit.after, false),
+        ] {
+            if let Some(line) = line {
+                let speaker = line
+                    .user_id
+                    .map(|id| format!("<@{}>", id))
+                    .unwrap_or_else(|| format!("SSRC {}", line.ssrc));
+                let text = match &filter {
+                    Some(filter) => sink::escape_markdown(&filter.apply(&line.text)),
+                    None => sink::escape_markdown(&line.text),
+                };
+                if is_match {
+                    entry.push_str(&format!("\n> **{}: {}**", speaker, text));
                 } else {
-                    let _ = msg
-                        .reply(&ctx.http, "❌ This command must be used in a server!")
-                        .await;
+                    entry.push_str(&format!("\n> {}: {}", speaker, text));
                 }
             }
-            "!leave" => {
-                if let Some(guild_id) = msg.guild_id {
-                    let manager = songbird::get(&ctx).await.expect("Songbird not initialized");
-
-                    match manager.remove(guild_id).await {
-                        Ok(_) => {
-                            let _ = msg.reply(&ctx.http, "👋 Left the voice channel!").await;
-                        }
-                        Err(_) => {
-                            let _ = msg
-                                .reply(&ctx.http, "❌ Failed to leave voice channel!")
-                                .await;
-                        }
+        }
+
+        if reply.len() + entry.len() > MAX_REPLY_LEN {
+            break;
+        }
+        reply.push_str(&entry);
+    }
+    reply
+}
+
+fn parse_user_mention(arg: &str) -> Option<UserId> {
+    let id = arg.strip_prefix("<@")?.strip_suffix('>')?;
+    let id = id.strip_prefix('!').unwrap_or(id);
+    id.parse::<u64>()
+        .ok()
+        .filter(|id| *id != 0)
+        .map(UserId::new)
+}
+
+/// Shows the guild's history settings, or changes the author's consent or the retention period.
+async fn configure_history(
+    ctx: &Context,
+    guild_id: GuildId,
+    user_id: UserId,
+    invocation: &Invocation,
+) -> String {
+    let data = ctx.data.read().await;
+    let (Some(history), Some(settings), Some(config)) = (
+        data.get::<HistoryKey>(),
+        data.get::<GuildSettingsKey>(),
+        data.get::<ConfigKey>(),
+    ) else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    let result = match invocation.args().as_slice() {
+        [] => {
+            let guild = settings.get(guild_id);
+            let retention = guild
+                .history_retention_days
+                .unwrap_or(config.history_retention_days);
+            let consent = if guild.history_opt_outs.contains(&user_id.get()) {
+                "opted out"
+            } else {
+                "included"
+            };
+            return format!(
+                "Transcript history is kept for {} days\nYour utterances: {}\n{}",
+                retention,
+                consent,
+                invocation.usage()
+            );
+        }
+        ["opt-out"] => {
+            let result = settings.update(guild_id, |guild| {
+                if !guild.history_opt_outs.contains(&user_id.get()) {
+                    guild.history_opt_outs.push(user_id.get());
+                }
+            });
+            if result.is_ok() {
+                return match history.delete_user(guild_id, user_id).await {
+                    Ok(deleted) => format!(
+                        "✅ Opted out of transcript history; deleted {} stored utterances",
+                        deleted
+                    ),
+                    Err(e) => {
+                        warn!(guild_id = guild_id.get(), "Failed to delete history: {}", e);
+                        String::from(
+                            "⚠️ Opted out, but deleting your stored utterances failed; try again",
+                        )
                     }
-                } else {
-                    let _ = msg
-                        .reply(&ctx.http, "❌ This command must be used in a server!")
-                        .await;
+                };
+            }
+            result
+        }
+        ["opt-in"] => settings.update(guild_id, |guild| {
+            guild.history_opt_outs.retain(|id| *id != user_id.get());
+        }),
+        ["retention", "default"] => {
+            settings.update(guild_id, |guild| guild.history_retention_days = None)
+        }
+        ["retention", days] => match days.parse::<u32>() {
+            Ok(days) if days > 0 => {
+                settings.update(guild_id, |guild| guild.history_retention_days = Some(days))
+            }
+            _ => return format!("❌ `{}` is not a number of days", days),
+        },
+        _ => return invocation.usage(),
+    };
+
+    match result {
+        Ok(()) => String::from("✅ History settings updated"),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+/// Shows or sets the languages captions are translated into; `translate off` stops it.
+async fn configure_translation(
+    ctx: &Context,
+    guild_id: GuildId,
+    invocation: &Invocation,
+) -> String {
+    let args = invocation.args();
+    let data = ctx.data.read().await;
+    let Some(settings) = data.get::<GuildSettingsKey>() else {
+        return String::from("❌ Bot not properly initialized");
+    };
+    let available = data.contains_key::<TranslationServiceKey>();
+
+    if args.is_empty() {
+        let targets = settings.get(guild_id).translation_targets;
+        let mut reply = format!(
+            "Translation: {}",
+            if targets.is_empty() {
+                String::from("off")
+            } else {
+                targets.join(", ")
+            }
+        );
+        if !available {
+            reply.push_str("\n⚠️ No translation server is configured for this bot");
+        }
+        return reply;
+    }
+
+    let targets: Vec<String> = if args == ["off"] {
+        Vec::new()
+    } else {
+        args.iter().map(|code| code.to_lowercase()).collect()
+    };
+    if let Some(invalid) = targets.iter().find(|code| {
+        !(2..=3).contains(&code.len()) || !code.chars().all(|c| c.is_ascii_lowercase())
+    }) {
+        return format!(
+            "❌ `{}` is not a language code, use codes like `de` or `es`",
+            invalid
+        );
+    }
+
+    let description = if targets.is_empty() {
+        String::from("off")
+    } else {
+        targets.join(", ")
+    };
+    match settings.update(guild_id, |guild| guild.translation_targets = targets) {
+        Ok(()) if !available && description != "off" => format!(
+            "✅ Translation set to {}, but no translation server is configured for this bot",
+            description
+        ),
+        Ok(()) => format!(
+            "✅ Translation set to {} (applies from the next `{}join`)",
+            description, invocation.prefix
+        ),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+/// Shows or changes the guild's profanity filter; changes apply from the next join.
+async fn configure_profanity(ctx: &Context, guild_id: GuildId, invocation: &Invocation) -> String {
+    let args = invocation.args();
+    let data = ctx.data.read().await;
+    let Some(settings) = data.get::<GuildSettingsKey>() else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    let words: Vec<String> = args
+        .iter()
+        .skip(1)
+        .map(|word| word.to_lowercase())
+        .collect();
+    let result = match args.as_slice() {
+        [] => {
+            let profanity = settings.get(guild_id).profanity;
+            return format!(
+                "Profanity filter: {}\nBlocked: {}\nAllowed: {}",
+                profanity.mode.name(),
+                describe_words(&profanity.blocked),
+                describe_words(&profanity.allowed)
+            );
+        }
+        ["mode", mode] => match ProfanityMode::parse(mode) {
+            Some(mode) => settings.update(guild_id, |guild| guild.profanity.mode = mode),
+            None => {
+                return format!(
+                    "❌ Unknown mode `{}`. Modes: {}",
+                    mode,
+                    ProfanityMode::NAMES.join(", ")
+                )
+            }
+        },
+        ["block", _, ..] => settings.update(guild_id, |guild| {
+            let profanity = &mut guild.profanity;
+            profanity.allowed.retain(|word| !words.contains(word));
+            for word in &words {
+                if !profanity.blocked.contains(word) {
+                    profanity.blocked.push(word.clone());
                 }
             }
-            _ => {}
+        }),
+        ["allow", _, ..] => settings.update(guild_id, |guild| {
+            let profanity = &mut guild.profanity;
+            profanity.blocked.retain(|word| !words.contains(word));
+            for word in &words {
+                if !profanity.allowed.contains(word) {
+                    profanity.allowed.push(word.clone());
+                }
+            }
+        }),
+        _ => return invocation.usage(),
+    };
+
+    match result {
+        Ok(()) => format!(
+            "✅ Profanity filter updated (applies from the next `{}join`)",
+            invocation.prefix
+        ),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+/// Shows or sets the guild's transcription mode; changes apply from the next join.
+async fn configure_mode(ctx: &Context, guild_id: GuildId, invocation: &Invocation) -> String {
+    let data = ctx.data.read().await;
+    let Some(settings) = data.get::<GuildSettingsKey>() else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    let mode = match invocation.args().as_slice() {
+        [] => {
+            return format!(
+                "Transcription mode: {}\nModes: {}",
+                settings.get(guild_id).transcription_mode.name(),
+                TranscriptionMode::NAMES.join(", ")
+            )
+        }
+        [mode] => match TranscriptionMode::parse(mode) {
+            Some(mode) => mode,
+            None => {
+                return format!(
+                    "❌ Unknown mode `{}`. Modes: {}",
+                    mode.replace('`', "'"),
+                    TranscriptionMode::NAMES.join(", ")
+                )
+            }
+        },
+        _ => return invocation.usage(),
+    };
+
+    match settings.update(guild_id, |guild| guild.transcription_mode = mode) {
+        Ok(()) => format!(
+            "✅ Transcription mode set to {} (applies from the next `{}join`)",
+            mode.name(),
+            invocation.prefix
+        ),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
         }
     }
 }
 
+/// Shows or toggles noise suppression; changes apply from the next join.
+async fn configure_denoise(ctx: &Context, guild_id: GuildId, invocation: &Invocation) -> String {
+    let data = ctx.data.read().await;
+    let Some(settings) = data.get::<GuildSettingsKey>() else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    let enabled = match invocation.args().as_slice() {
+        [] => {
+            let state = if settings.get(guild_id).noise_suppression {
+                "on"
+            } else {
+                "off"
+            };
+            return format!("Noise suppression: {}\n{}", state, invocation.usage());
+        }
+        ["on"] => true,
+        ["off"] => false,
+        _ => return invocation.usage(),
+    };
+
+    match settings.update(guild_id, |guild| guild.noise_suppression = enabled) {
+        Ok(()) => format!(
+            "✅ Noise suppression {} (applies from the next `{}join`)",
+            if enabled { "enabled" } else { "disabled" },
+            invocation.prefix
+        ),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+const MIN_ENDPOINT_SECS: f64 = 0.2;
+const MAX_ENDPOINT_SECS: f64 = 10.0;
+
+/// Shows or sets the bounds on the silence that ends an utterance; changes apply from the
+/// next join.
+async fn configure_endpoint(ctx: &Context, guild_id: GuildId, invocation: &Invocation) -> String {
+    let data = ctx.data.read().await;
+    let Some(settings) = data.get::<GuildSettingsKey>() else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    let endpoint = match invocation.args().as_slice() {
+        [] => {
+            let endpoint = settings.get(guild_id).endpoint;
+            return format!(
+                "Utterances end after {} to {} of silence, depending on the speaker\n{}",
+                format_seconds(endpoint.min_ms),
+                format_seconds(endpoint.max_ms),
+                invocation.usage()
+            );
+        }
+        ["default"] => EndpointSettings::default(),
+        [min, max] => {
+            let parse = |secs: &str| {
+                secs.trim_end_matches('s')
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|secs| (MIN_ENDPOINT_SECS..=MAX_ENDPOINT_SECS).contains(secs))
+                    .map(|secs| (secs * 1000.0).round() as u32)
+            };
+            match (parse(min), parse(max)) {
+                (Some(min_ms), Some(max_ms)) if min_ms <= max_ms => {
+                    EndpointSettings { min_ms, max_ms }
+                }
+                _ => {
+                    return format!(
+                        "❌ Give the shortest and longest silence in seconds, between {} and {}, e.g. `{}endpoint 0.5 3`",
+                        MIN_ENDPOINT_SECS, MAX_ENDPOINT_SECS, invocation.prefix
+                    )
+                }
+            }
+        }
+        _ => return invocation.usage(),
+    };
+
+    match settings.update(guild_id, |guild| guild.endpoint = endpoint) {
+        Ok(()) => format!(
+            "✅ Utterances will end after {} to {} of silence (applies from the next `{}join`)",
+            format_seconds(endpoint.min_ms),
+            format_seconds(endpoint.max_ms),
+            invocation.prefix
+        ),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+fn format_seconds(ms: u32) -> String {
+    format!("{}s", f64::from(ms) / 1000.0)
+}
+
+fn describe_words(words: &[String]) -> String {
+    if words.is_empty() {
+        String::from("none")
+    } else {
+        words
+            .iter()
+            .map(|word| format!("`{}`", word))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Shows the guild's post-processing chain, or replaces it when stages are given.
+///
+/// `postprocess none` clears the chain; changes apply from the next join.
+async fn configure_post_processing(
+    ctx: &Context,
+    guild_id: GuildId,
+    invocation: &Invocation,
+) -> String {
+    let stages = invocation.args.clone();
+    let data = ctx.data.read().await;
+    let Some(settings) = data.get::<GuildSettingsKey>() else {
+        return String::from("❌ Bot not properly initialized");
+    };
+
+    if stages.is_empty() {
+        let current = settings.get(guild_id).post_processors;
+        return format!(
+            "Post-processing: {}\nAvailable stages: {}",
+            describe_stages(&current),
+            postprocess::STAGE_NAMES.join(", ")
+        );
+    }
+
+    let stages = if stages == ["none"] {
+        Vec::new()
+    } else {
+        stages
+    };
+    if let Some(unknown) = stages
+        .iter()
+        .find(|stage| !postprocess::STAGE_NAMES.contains(&stage.as_str()))
+    {
+        return format!(
+            "❌ Unknown stage `{}`. Available stages: {}",
+            unknown,
+            postprocess::STAGE_NAMES.join(", ")
+        );
+    }
+
+    let description = describe_stages(&stages);
+    match settings.update(guild_id, |guild| guild.post_processors = stages) {
+        Ok(()) => format!(
+            "✅ Post-processing set to {} (applies from the next `{}join`)",
+            description, invocation.prefix
+        ),
+        Err(e) => {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to save guild settings: {}", e
+            );
+            String::from("❌ Failed to save settings")
+        }
+    }
+}
+
+fn describe_stages(stages: &[String]) -> String {
+    if stages.is_empty() {
+        String::from("none")
+    } else {
+        stages.join(" → ")
+    }
+}
+
+async fn set_gateway_connected(ctx: &Context, connected: bool) {
+    if let Some(health) = ctx.data.read().await.get::<HealthKey>() {
+        health.set_gateway_connected(connected);
+    }
+}
+
+async fn status_report(ctx: &Context, guild_id: GuildId) -> String {
+    let data = ctx.data.read().await;
+    let status = data
+        .get::<SessionsKey>()
+        .and_then(|sessions| sessions.status(guild_id));
+
+    let mut report = match status {
+        Some(status) => {
+            let connection = if status.driver_connected {
+                "🟢 Connected"
+            } else {
+                "🔴 Voice driver disconnected"
+            };
+            let latency = status
+                .recent_latency
+                .map(|latency| format!("{} ms", latency.as_millis()))
+                .unwrap_or_else(|| String::from("n/a"));
+
+            let mut report = format!(
+                "{}\nChannel: <#{}>\nUptime: {}\nTracked speakers: {}\nRecent latency: {}",
+                connection,
+                status.channel_id,
+                format_duration(status.uptime),
+                status.speakers,
+                latency
+            );
+            if let Some(tts_channel) = status.tts_channel {
+                report.push_str(&format!("\nReading aloud: <#{}>", tts_channel));
+            }
+            report
+        }
+        None => String::from("⚪ Not in a voice channel"),
+    };
+
+    if let Some(pool) = data.get::<RecognizerPoolKey>() {
+        report.push_str(&format!("\nModel: {}", pool.model_name()));
+    }
+    report
+}
+
 pub async fn join_voice_channel(
     ctx: &Context,
     guild_id: GuildId,
     channel_id: ChannelId,
-    model: Arc<Model>,
+    text_channel_id: ChannelId,
+    pool: Arc<RecognizerPool>,
 ) -> Result<(), String> {
     let manager = songbird::get(ctx).await.expect("Songbird not initialized");
 
+    // A previous session in this guild is replaced; deliver what it still holds
+    let previous = ctx
+        .data
+        .read()
+        .await
+        .get::<SessionsKey>()
+        .and_then(|sessions| sessions.remove(guild_id));
+    if let Some(previous) = previous {
+        previous.close().await;
+    }
+
     let handler_lock = manager
         .join(guild_id, channel_id)
         .await
         .map_err(|e| format!("Join error: {:?}", e))?;
 
-    let mut handler = handler_lock.lock().await;
-    handler.remove_all_global_events();
+    let context = SessionContext {
+        guild_id,
+        channel_id,
+        session_id: NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed),
+        started_at: SystemTime::now(),
+    };
+    let span = info_span!(
+        "session",
+        guild_id = context.guild_id.get(),
+        channel_id = context.channel_id.get(),
+        session_id = context.session_id
+    );
+    span.in_scope(|| info!("Joined voice channel"));
+
+    // Nothing below holds the data lock across an await
+    let (config, settings) = {
+        let data = ctx.data.read().await;
+        let config = data
+            .get::<ConfigKey>()
+            .cloned()
+            .ok_or("Bot not properly initialized")?;
+        let settings = data
+            .get::<GuildSettingsKey>()
+            .map(|settings| settings.get(guild_id))
+            .unwrap_or_default();
+        (config, settings)
+    };
+    let profanity = &settings.profanity;
+    let profanity_filter =
+        |remove| ProfanityFilter::new(remove, &profanity.blocked, &profanity.allowed);
+
+    let caption_filter =
+        (profanity.mode == ProfanityMode::CaptionsOnly).then(|| profanity_filter(false));
+    let session_filter = match profanity.mode {
+        ProfanityMode::Mask => Some(profanity_filter(false)),
+        ProfanityMode::Remove => Some(profanity_filter(true)),
+        ProfanityMode::Off | ProfanityMode::CaptionsOnly => None,
+    };
+    // Post as the speakers through a webhook, or as the bot without Manage Webhooks
+    let bot_user_id = ctx.cache.current_user().id;
+    let captions: Box<dyn TranscriptSink> = match WebhookSink::connect(
+        ctx.http.clone(),
+        guild_id,
+        text_channel_id,
+        bot_user_id,
+        caption_filter.clone(),
+    )
+    .await
+    {
+        Ok(webhook) => Box::new(webhook),
+        Err(e) => {
+            span.in_scope(|| warn!("Posting captions as the bot: {}", e));
+            Box::new(ChannelSink::new(
+                ctx.http.clone(),
+                text_channel_id,
+                caption_filter,
+            ))
+        }
+    };
 
-    let receiver = Receiver::new(model);
+    let data = ctx.data.read().await;
+    let live = data.get::<LiveStreamKey>().cloned().unwrap_or_default();
+    let mut sinks: Vec<Box<dyn TranscriptSink>> = vec![
+        captions,
+        Box::new(ArchiveSink::new(config.transcript_dir.clone(), &context)),
+        Box::new(LiveStreamSink::new(live.clone(), session_filter.is_none())),
+    ];
+    if let (Some(history), Some(settings)) =
+        (data.get::<HistoryKey>(), data.get::<GuildSettingsKey>())
+    {
+        sinks.push(Box::new(HistorySink::new(
+            history.clone(),
+            settings.clone(),
+        )));
+    }
+    let language = vosk_model::model_language(pool.model_name()).unwrap_or_else(|| {
+        warn!(
+            model = pool.model_name(),
+            "Unknown model language, assuming English"
+        );
+        String::from("en")
+    });
+    let mut post_processors = postprocess::build_chain(
+        &settings.post_processors,
+        &language,
+        config.punctuation_model_cmd.as_deref(),
+    );
+    // Runs last so it also sees words produced by earlier stages
+    if let Some(filter) = &session_filter {
+        post_processors.push(Arc::new(filter.clone()));
+    }
+    let translator = data
+        .get::<TranslationServiceKey>()
+        .filter(|_| !settings.translation_targets.is_empty())
+        .map(|service| {
+            SessionTranslator::new(
+                service.clone(),
+                language.clone(),
+                settings.translation_targets.clone(),
+            )
+        });
+    let transcripts = sink::spawn_delivery(post_processors, translator, sinks, span.clone());
 
+    // Noise suppression is opt-in per guild with the `denoise` command
+    let stages: Vec<StageKind> = config
+        .audio_pipeline
+        .iter()
+        .copied()
+        .filter(|stage| *stage != StageKind::Denoise || settings.noise_suppression)
+        .collect();
+    let receiver = Receiver::new(
+        pool,
+        ReceiverOptions {
+            idle_timeout: config.speaker_idle_timeout,
+            mode: settings.transcription_mode,
+            stages,
+            max_utterance: config.max_utterance,
+            min_silence: Duration::from_millis(settings.endpoint.min_ms.into()),
+            max_silence: Duration::from_millis(settings.endpoint.max_ms.into()),
+            profanity: session_filter,
+        },
+        context,
+        transcripts,
+        live,
+        span,
+    );
+
+    if let Some(sessions) = data.get::<SessionsKey>() {
+        sessions.insert(
+            guild_id,
+            Session {
+                channel_id,
+                started_at: Instant::now(),
+                receiver: receiver.clone(),
+                tts: None,
+            },
+        );
+    }
+    drop(data);
+
+    let mut handler = handler_lock.lock().await;
+    handler.remove_all_global_events();
     handler.add_global_event(
-        Event::Core(songbird::CoreEvent::SpeakingStateUpdate.into()),
+        Event::Core(songbird::CoreEvent::SpeakingStateUpdate),
         receiver.clone(),
     );
     handler.add_global_event(
-        Event::Core(songbird::CoreEvent::ClientDisconnect.into()),
+        Event::Core(songbird::CoreEvent::ClientDisconnect),
         receiver.clone(),
     );
     handler.add_global_event(
-        Event::Core(songbird::CoreEvent::DriverDisconnect.into()),
+        Event::Core(songbird::CoreEvent::DriverConnect),
         receiver.clone(),
     );
-    handler.add_global_event(Event::Core(songbird::CoreEvent::VoiceTick.into()), receiver);
+    handler.add_global_event(
+        Event::Core(songbird::CoreEvent::DriverReconnect),
+        receiver.clone(),
+    );
+    handler.add_global_event(
+        Event::Core(songbird::CoreEvent::DriverDisconnect),
+        receiver.clone(),
+    );
+    handler.add_global_event(Event::Core(songbird::CoreEvent::VoiceTick), receiver);
 
     Ok(())
 }
-
-pub struct VoskModelKey;
-impl TypeMapKey for VoskModelKey {
-    type Value = Arc<Model>;
-}
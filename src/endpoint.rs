@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+/// Silence that ends an utterance until a speaker's pauses have been learned.
+const INITIAL_TIMEOUT: Duration = Duration::from_millis(1500);
+/// Shorter gaps between packets are jitter or breathing, not pauses.
+const MIN_PAUSE: Duration = Duration::from_millis(150);
+/// Pauses seen before the learned timeout replaces the initial one.
+const WARMUP_PAUSES: u32 = 3;
+/// Weight of each new pause in the running mean and variance.
+const LEARNING_RATE: f64 = 0.1;
+/// The timeout sits this many standard deviations above the speaker's mean pause, so most
+/// of their pauses within a turn don't end it.
+const DEVIATIONS: f64 = 2.0;
+/// Share of the timeout to wait once Vosk has detected the end of a phrase.
+const ENDPOINT_FACTOR: f64 = 0.5;
+
+/// Decides how much silence ends one speaker's utterance.
+///
+/// Learns the speaker's typical pause length and waits a little longer than that, within
+/// the guild's bounds. Fast talkers get short timeouts and slow ones long timeouts.
+pub struct Endpointer {
+    min: Duration,
+    max: Duration,
+    /// Running mean and variance of pause lengths, in seconds.
+    mean: f64,
+    variance: f64,
+    pauses: u32,
+}
+
+impl Endpointer {
+    /// Bounds given the wrong way round, e.g. from a hand-edited settings file, are swapped.
+    pub fn new(min: Duration, max: Duration) -> Self {
+        let (min, max) = if min <= max { (min, max) } else { (max, min) };
+        Self {
+            min,
+            max,
+            mean: INITIAL_TIMEOUT.as_secs_f64(),
+            variance: 0.0,
+            pauses: 0,
+        }
+    }
+
+    /// Learns from a pause within an utterance. Silence between utterances isn't passed in:
+    /// it is at least the timeout, so learning from it would push the timeout up to the max.
+    pub fn on_pause(&mut self, pause: Duration) {
+        if pause < MIN_PAUSE || pause > self.max {
+            return;
+        }
+        let pause = pause.as_secs_f64();
+        if self.pauses == 0 {
+            self.mean = pause;
+        } else {
+            let delta = pause - self.mean;
+            self.mean += LEARNING_RATE * delta;
+            self.variance = (1.0 - LEARNING_RATE) * (self.variance + LEARNING_RATE * delta * delta);
+        }
+        self.pauses = self.pauses.saturating_add(1);
+    }
+
+    /// Silence that ends the utterance. `at_endpoint` is whether Vosk has detected the end of
+    /// a phrase since the speaker's last audio, which makes a shorter wait safe.
+    pub fn timeout(&self, at_endpoint: bool) -> Duration {
+        let learned = if self.pauses < WARMUP_PAUSES {
+            INITIAL_TIMEOUT.as_secs_f64()
+        } else {
+            self.mean + DEVIATIONS * self.variance.sqrt()
+        };
+        let timeout = if at_endpoint {
+            learned * ENDPOINT_FACTOR
+        } else {
+            learned
+        };
+        Duration::from_secs_f64(timeout).clamp(self.min, self.max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpointer() -> Endpointer {
+        Endpointer::new(Duration::from_millis(200), Duration::from_secs(3))
+    }
+
+    fn assert_near(actual: Duration, expected_ms: u64) {
+        let difference = actual.as_secs_f64() * 1000.0 - expected_ms as f64;
+        assert!(
+            difference.abs() < 1.0,
+            "{:?} is not {} ms",
+            actual,
+            expected_ms
+        );
+    }
+
+    #[test]
+    fn uses_the_initial_timeout_until_warmed_up() {
+        let mut endpointer = endpointer();
+        for _ in 1..WARMUP_PAUSES {
+            endpointer.on_pause(Duration::from_millis(400));
+        }
+        assert_near(endpointer.timeout(false), 1500);
+        assert_near(endpointer.timeout(true), 750);
+    }
+
+    #[test]
+    fn learns_the_speakers_pauses() {
+        let mut endpointer = endpointer();
+        for _ in 0..WARMUP_PAUSES {
+            endpointer.on_pause(Duration::from_millis(400));
+        }
+        assert_near(endpointer.timeout(false), 400);
+        assert_near(endpointer.timeout(true), 200);
+
+        // Varied pauses push the timeout above their mean
+        for pause in [300, 700, 300, 700, 300, 700] {
+            endpointer.on_pause(Duration::from_millis(pause));
+        }
+        let timeout = endpointer.timeout(false);
+        assert!(timeout > Duration::from_millis(600), "{:?}", timeout);
+    }
+
+    #[test]
+    fn ignores_jitter_and_long_silences() {
+        let mut endpointer = endpointer();
+        for _ in 0..10 {
+            endpointer.on_pause(Duration::from_millis(50));
+            endpointer.on_pause(Duration::from_secs(30));
+        }
+        assert_near(endpointer.timeout(false), 1500);
+    }
+
+    #[test]
+    fn swaps_reversed_bounds() {
+        let endpointer = Endpointer::new(Duration::from_secs(3), Duration::from_secs(2));
+        assert_near(endpointer.timeout(false), 2000);
+        assert_near(endpointer.timeout(true), 2000);
+    }
+
+    #[test]
+    fn stays_within_bounds() {
+        let mut endpointer = Endpointer::new(Duration::from_secs(2), Duration::from_secs(3));
+        assert_near(endpointer.timeout(true), 2000);
+        for _ in 0..WARMUP_PAUSES {
+            endpointer.on_pause(Duration::from_millis(200));
+        }
+        assert_near(endpointer.timeout(false), 2000);
+
+        let mut endpointer = Endpointer::new(Duration::ZERO, Duration::from_secs(1));
+        assert_near(endpointer.timeout(false), 1000);
+        for _ in 0..WARMUP_PAUSES {
+            endpointer.on_pause(Duration::from_millis(200));
+        }
+        assert_near(endpointer.timeout(true), 100);
+    }
+}
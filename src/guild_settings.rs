@@ -0,0 +1,203 @@
+use crate::permissions::CommandAccess;
+use crate::postprocess;
+use serde::{Deserialize, Serialize};
+use serenity::model::id::GuildId;
+use serenity::prelude::TypeMapKey;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+/// Per-guild preferences, persisted as JSON alongside the bot.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildSettings {
+    /// Text post-processing stages applied to final transcripts, in order.
+    pub post_processors: Vec<String>,
+    pub profanity: ProfanitySettings,
+    /// Languages captions are translated into, as ISO 639-1 codes.
+    pub translation_targets: Vec<String>,
+    /// Users whose utterances are kept out of the searchable history.
+    pub history_opt_outs: Vec<u64>,
+    /// Days history is kept for; the bot-wide default when unset.
+    pub history_retention_days: Option<u32>,
+    /// Per-command role and user allowlists, keyed by command name.
+    pub command_permissions: BTreeMap<String, CommandAccess>,
+    /// Command prefix; the bot-wide default when unset.
+    pub prefix: Option<String>,
+    /// Read-aloud voices members picked with `!voice`, keyed by user.
+    pub tts_voices: BTreeMap<u64, String>,
+    pub transcription_mode: TranscriptionMode,
+    /// Suppress background noise in speakers' audio before recognition.
+    pub noise_suppression: bool,
+    pub endpoint: EndpointSettings,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            post_processors: postprocess::default_stages(),
+            profanity: ProfanitySettings::default(),
+            translation_targets: Vec::new(),
+            history_opt_outs: Vec::new(),
+            history_retention_days: None,
+            command_permissions: BTreeMap::new(),
+            prefix: None,
+            tts_voices: BTreeMap::new(),
+            transcription_mode: TranscriptionMode::default(),
+            noise_suppression: false,
+            endpoint: EndpointSettings::default(),
+        }
+    }
+}
+
+/// Bounds on the silence that ends an utterance; each speaker's is learned in between.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EndpointSettings {
+    pub min_ms: u32,
+    pub max_ms: u32,
+}
+
+impl Default for EndpointSettings {
+    fn default() -> Self {
+        Self {
+            min_ms: 500,
+            max_ms: 3000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfanitySettings {
+    pub mode: ProfanityMode,
+    /// Words masked in addition to the built-in list; a trailing `*` matches any suffix.
+    pub blocked: Vec<String>,
+    /// Words from the built-in list this guild doesn't want masked.
+    pub allowed: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfanityMode {
+    #[default]
+    Off,
+    /// Replace all but the first letter with asterisks everywhere.
+    Mask,
+    /// Drop profane words everywhere.
+    Remove,
+    /// Mask posted captions but keep archives verbatim.
+    CaptionsOnly,
+}
+
+impl ProfanityMode {
+    pub const NAMES: &'static [&'static str] = &["off", "mask", "remove", "captions-only"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "off" => Some(Self::Off),
+            "mask" => Some(Self::Mask),
+            "remove" => Some(Self::Remove),
+            "captions-only" => Some(Self::CaptionsOnly),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Mask => "mask",
+            Self::Remove => "remove",
+            Self::CaptionsOnly => "captions-only",
+        }
+    }
+}
+
+/// How a session's audio is turned into text.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TranscriptionMode {
+    /// A recognizer per speaker; most accurate.
+    #[default]
+    PerSpeaker,
+    /// One recognizer on everyone's audio mixed together, with each piece of text attributed
+    /// to the loudest speaker. Much cheaper with many speakers, but overlapping speech suffers.
+    Mixed,
+}
+
+impl TranscriptionMode {
+    pub const NAMES: &'static [&'static str] = &["per-speaker", "mixed"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "per-speaker" => Some(Self::PerSpeaker),
+            "mixed" => Some(Self::Mixed),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PerSpeaker => "per-speaker",
+            Self::Mixed => "mixed",
+        }
+    }
+}
+
+pub struct GuildSettingsStore {
+    path: PathBuf,
+    guilds: Mutex<HashMap<u64, GuildSettings>>,
+}
+
+impl GuildSettingsStore {
+    pub fn load(path: PathBuf) -> Result<Arc<Self>, Box<dyn Error>> {
+        let guilds = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .map_err(|e| format!("Invalid guild settings in {}: {}", path.display(), e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        };
+
+        Ok(Arc::new(Self {
+            path,
+            guilds: Mutex::new(guilds),
+        }))
+    }
+
+    pub fn get(&self, guild_id: GuildId) -> GuildSettings {
+        self.guilds
+            .lock()
+            .unwrap()
+            .get(&guild_id.get())
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Applies `change` to a guild's settings and writes the store back to disk.
+    pub fn update(
+        &self,
+        guild_id: GuildId,
+        change: impl FnOnce(&mut GuildSettings),
+    ) -> Result<(), String> {
+        let mut guilds = self.guilds.lock().unwrap();
+        change(guilds.entry(guild_id.get()).or_default());
+
+        let contents = serde_json::to_string_pretty(&*guilds).map_err(|e| e.to_string())?;
+
+        // Write then rename so a crash never leaves a truncated file behind
+        let partial = self.path.with_extension("json.partial");
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+        }
+        fs::write(&partial, contents).map_err(|e| format!("{}: {}", partial.display(), e))?;
+        fs::rename(&partial, &self.path).map_err(|e| format!("{}: {}", self.path.display(), e))
+    }
+}
+
+pub struct GuildSettingsKey;
+impl TypeMapKey for GuildSettingsKey {
+    type Value = Arc<GuildSettingsStore>;
+}
@@ -0,0 +1,33 @@
+use serenity::prelude::TypeMapKey;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Process-wide readiness signals shared with the HTTP health endpoints.
+#[derive(Default)]
+pub struct Health {
+    gateway_connected: AtomicBool,
+    model_loaded: AtomicBool,
+}
+
+impl Health {
+    pub fn set_gateway_connected(&self, connected: bool) {
+        self.gateway_connected.store(connected, Ordering::Relaxed);
+    }
+
+    pub fn gateway_connected(&self) -> bool {
+        self.gateway_connected.load(Ordering::Relaxed)
+    }
+
+    pub fn set_model_loaded(&self, loaded: bool) {
+        self.model_loaded.store(loaded, Ordering::Relaxed);
+    }
+
+    pub fn model_loaded(&self) -> bool {
+        self.model_loaded.load(Ordering::Relaxed)
+    }
+}
+
+pub struct HealthKey;
+impl TypeMapKey for HealthKey {
+    type Value = Arc<Health>;
+}
@@ -0,0 +1,436 @@
+use crate::guild_settings::GuildSettingsStore;
+use crate::sink::{Transcript, TranscriptSink};
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::async_trait;
+use serenity::model::id::{GuildId, UserId};
+use serenity::prelude::TypeMapKey;
+use std::error::Error;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS utterances (
+        id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL,
+        channel_id INTEGER NOT NULL,
+        session_id INTEGER NOT NULL,
+        session_started_at INTEGER NOT NULL,
+        user_id INTEGER,
+        ssrc INTEGER NOT NULL,
+        started_at INTEGER NOT NULL,
+        text TEXT NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS utterances_guild_time ON utterances (guild_id, started_at);
+    CREATE INDEX IF NOT EXISTS utterances_session
+        ON utterances (guild_id, session_started_at, session_id, started_at);
+
+    CREATE VIRTUAL TABLE IF NOT EXISTS utterances_fts USING fts5(
+        text,
+        content = 'utterances',
+        content_rowid = 'id',
+        tokenize = 'porter unicode61'
+    );
+    CREATE TRIGGER IF NOT EXISTS utterances_insert AFTER INSERT ON utterances BEGIN
+        INSERT INTO utterances_fts (rowid, text) VALUES (new.id, new.text);
+    END;
+    CREATE TRIGGER IF NOT EXISTS utterances_delete AFTER DELETE ON utterances BEGIN
+        INSERT INTO utterances_fts (utterances_fts, rowid, text)
+            VALUES ('delete', old.id, old.text);
+    END;
+";
+
+const PURGE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// A stored utterance.
+pub struct Line {
+    pub user_id: Option<UserId>,
+    pub ssrc: u32,
+    pub text: String,
+    /// Milliseconds since the Unix epoch.
+    pub started_at: u64,
+    pub session_started_at: u64,
+}
+
+impl Line {
+    /// Offset of the utterance from the start of its session, matching the archive timestamps.
+    pub fn offset(&self) -> Duration {
+        Duration::from_millis(self.started_at.saturating_sub(self.session_started_at))
+    }
+}
+
+pub struct SearchHit {
+    pub line: Line,
+    pub before: Option<Line>,
+    pub after: Option<Line>,
+}
+
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub user_id: Option<UserId>,
+    /// Only utterances at or after this time, in milliseconds since the Unix epoch.
+    pub since: u64,
+    pub limit: usize,
+}
+
+/// Full-text searchable transcript history in SQLite.
+#[derive(Clone)]
+pub struct History {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl History {
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        conn.execute_batch(SCHEMA)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Runs a query on the blocking pool so SQLite never stalls the async workers.
+    async fn run<T, F>(&self, query: F) -> Result<T, String>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> rusqlite::Result<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        tokio::task::spawn_blocking(move || query(&conn.lock().unwrap()))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn insert(&self, transcript: &Transcript) -> Result<(), String> {
+        let session = transcript.session;
+        let user_id = transcript.user_id.map(|id| id.get() as i64);
+        let ssrc = transcript.ssrc;
+        let started_at = unix_millis(transcript.started_at) as i64;
+        let text = transcript.text.clone();
+
+        self.run(move |conn| {
+            conn.execute(
+                "INSERT INTO utterances
+                    (guild_id, channel_id, session_id, session_started_at, user_id, ssrc,
+                     started_at, text)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                params![
+                    session.guild_id.get() as i64,
+                    session.channel_id.get() as i64,
+                    session.session_id as i64,
+                    unix_millis(session.started_at) as i64,
+                    user_id,
+                    ssrc,
+                    started_at,
+                    text
+                ],
+            )
+            .map(|_| ())
+        })
+        .await
+    }
+
+    pub async fn search(
+        &self,
+        guild_id: GuildId,
+        query: SearchQuery,
+    ) -> Result<Vec<SearchHit>, String> {
+        // Quote every term so user input can't use FTS query syntax; terms are ANDed
+        let match_expr = query
+            .terms
+            .iter()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let guild_id = guild_id.get() as i64;
+
+        self.run(move |conn| {
+            let mut statement = conn.prepare(
+                "SELECT u.id, u.session_id, u.session_started_at, u.user_id, u.ssrc,
+                        u.started_at, u.text
+                 FROM utterances_fts
+                 JOIN utterances u ON u.id = utterances_fts.rowid
+                 WHERE utterances_fts MATCH ?1
+                   AND u.guild_id = ?2
+                   AND (?3 IS NULL OR u.user_id = ?3)
+                   AND u.started_at >= ?4
+                 ORDER BY bm25(utterances_fts)
+                 LIMIT ?5",
+            )?;
+            let matches = statement
+                .query_map(
+                    params![
+                        match_expr,
+                        guild_id,
+                        query.user_id.map(|id| id.get() as i64),
+                        query.since as i64,
+                        query.limit as i64
+                    ],
+                    |row| {
+                        Ok((
+                            row.get::<_, i64>(0)?,
+                            row.get::<_, i64>(1)?,
+                            Line {
+                                session_started_at: row.get::<_, i64>(2)? as u64,
+                                user_id: row
+                                    .get::<_, Option<i64>>(3)?
+                                    .map(|id| UserId::new(id as u64)),
+                                ssrc: row.get(4)?,
+                                started_at: row.get::<_, i64>(5)? as u64,
+                                text: row.get(6)?,
+                            },
+                        ))
+                    },
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            matches
+                .into_iter()
+                .map(|(id, session_id, line)| {
+                    let neighbour = |before: bool| {
+                        let sql = if before {
+                            "SELECT user_id, ssrc, started_at, text FROM utterances
+                             WHERE guild_id = ?1 AND session_started_at = ?2 AND session_id = ?3
+                               AND (started_at < ?4 OR (started_at = ?4 AND id < ?5))
+                             ORDER BY started_at DESC, id DESC LIMIT 1"
+                        } else {
+                            "SELECT user_id, ssrc, started_at, text FROM utterances
+                             WHERE guild_id = ?1 AND session_started_at = ?2 AND session_id = ?3
+                               AND (started_at > ?4 OR (started_at = ?4 AND id > ?5))
+                             ORDER BY started_at, id LIMIT 1"
+                        };
+                        conn.query_row(
+                            sql,
+                            params![
+                                guild_id,
+                                line.session_started_at as i64,
+                                session_id,
+                                line.started_at as i64,
+                                id
+                            ],
+                            |row| {
+                                Ok(Line {
+                                    user_id: row
+                                        .get::<_, Option<i64>>(0)?
+                                        .map(|id| UserId::new(id as u64)),
+                                    ssrc: row.get(1)?,
+                                    started_at: row.get::<_, i64>(2)? as u64,
+                                    text: row.get(3)?,
+                                    session_started_at: line.session_started_at,
+                                })
+                            },
+                        )
+                        .optional()
+                    };
+
+                    Ok(SearchHit {
+                        before: neighbour(true)?,
+                        after: neighbour(false)?,
+                        line,
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Deletes everything a user said in a guild, e.g. when they opt out.
+    pub async fn delete_user(&self, guild_id: GuildId, user_id: UserId) -> Result<usize, String> {
+        self.run(move |conn| {
+            conn.execute(
+                "DELETE FROM utterances WHERE guild_id = ?1 AND user_id = ?2",
+                params![guild_id.get() as i64, user_id.get() as i64],
+            )
+        })
+        .await
+    }
+
+    async fn purge(&self, guild_id: i64, before: u64) -> Result<usize, String> {
+        self.run(move |conn| {
+            conn.execute(
+                "DELETE FROM utterances WHERE guild_id = ?1 AND started_at < ?2",
+                params![guild_id, before as i64],
+            )
+        })
+        .await
+    }
+
+    /// Periodically deletes history older than each guild's retention period.
+    pub fn spawn_retention(&self, settings: Arc<GuildSettingsStore>, default_retention_days: u32) {
+        let history = self.clone();
+
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(PURGE_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                let guilds = history
+                    .run(|conn| {
+                        let mut statement =
+                            conn.prepare("SELECT DISTINCT guild_id FROM utterances")?;
+                        let guilds = statement
+                            .query_map([], |row| row.get::<_, i64>(0))?
+                            .collect::<rusqlite::Result<Vec<_>>>();
+                        guilds
+                    })
+                    .await;
+                let guilds = match guilds {
+                    Ok(guilds) => guilds,
+                    Err(e) => {
+                        warn!("Failed to list guilds for history retention: {}", e);
+                        continue;
+                    }
+                };
+
+                for guild_id in guilds {
+                    let retention = settings
+                        .get(GuildId::new(guild_id as u64))
+                        .history_retention_days
+                        .unwrap_or(default_retention_days);
+                    match history.purge(guild_id, retention_cutoff(retention)).await {
+                        Ok(0) => {}
+                        Ok(purged) => info!(guild_id, purged, "Purged expired transcript history"),
+                        Err(e) => warn!(guild_id, "Failed to purge transcript history: {}", e),
+                    }
+                }
+            }
+        });
+    }
+}
+
+/// Oldest time still within a retention period, in milliseconds since the Unix epoch.
+pub fn retention_cutoff(retention_days: u32) -> u64 {
+    unix_millis(SystemTime::now()).saturating_sub(u64::from(retention_days) * 24 * 60 * 60 * 1000)
+}
+
+pub fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Parses a `YYYY-MM-DD` date (UTC) into milliseconds since the Unix epoch.
+pub fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.splitn(3, '-');
+    let year: i64 = parts.next()?.parse().ok()?;
+    let month: i64 = parts.next()?.parse().ok()?;
+    let day: i64 = parts.next()?.parse().ok()?;
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1970..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=days_in_month).contains(&day)
+    {
+        return None;
+    }
+
+    // Days since the epoch for a proleptic Gregorian date (Howard Hinnant's algorithm)
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    Some(days as u64 * 24 * 60 * 60 * 1000)
+}
+
+/// Records final transcripts in the history, skipping users who opted out.
+pub struct HistorySink {
+    history: History,
+    settings: Arc<GuildSettingsStore>,
+}
+
+impl HistorySink {
+    pub fn new(history: History, settings: Arc<GuildSettingsStore>) -> Self {
+        Self { history, settings }
+    }
+}
+
+#[async_trait]
+impl TranscriptSink for HistorySink {
+    fn name(&self) -> &'static str {
+        "history"
+    }
+
+    async fn deliver(&self, transcript: &Transcript) -> Result<(), String> {
+        // Without a known speaker there's no way to honour an opt-out, so don't keep it
+        let Some(user_id) = transcript.user_id else {
+            return Ok(());
+        };
+        let opted_out = self
+            .settings
+            .get(transcript.session.guild_id)
+            .history_opt_outs
+            .contains(&user_id.get());
+        if opted_out {
+            return Ok(());
+        }
+
+        self.history.insert(transcript).await
+    }
+}
+
+pub struct HistoryKey;
+impl TypeMapKey for HistoryKey {
+    type Value = History;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60 * 1000;
+
+    #[test]
+    fn parses_valid_dates() {
+        assert_eq!(parse_date("1970-01-01"), Some(0));
+        assert_eq!(parse_date("1970-01-02"), Some(DAY));
+        assert_eq!(parse_date("2000-03-01"), Some(11_017 * DAY));
+        assert_eq!(parse_date("2024-02-29"), Some(19_782 * DAY));
+        assert_eq!(parse_date("2026-10-18"), Some(20_744 * DAY));
+    }
+
+    #[test]
+    fn rejects_invalid_dates() {
+        for date in [
+            "",
+            "2026",
+            "2026-10",
+            "2026/10/18",
+            "18-10-2026",
+            "2026-1o-18",
+            "2026-00-10",
+            "2026-13-01",
+            "2026-10-00",
+            "2026-04-31",
+            "2023-02-29",
+            "1900-02-29",
+        ] {
+            assert_eq!(parse_date(date), None, "{}", date);
+        }
+    }
+
+    #[test]
+    fn accepts_only_the_supported_range() {
+        assert_eq!(parse_date("1969-12-31"), None);
+        assert_eq!(parse_date("10000-01-01"), None);
+        assert!(parse_date("9999-12-31").is_some());
+        assert!(parse_date("2000-02-29").is_some());
+        assert_eq!(
+            parse_date("2026-12-31").map(|date| date + DAY),
+            parse_date("2027-01-01")
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use crate::health::Health;
+use crate::live_stream::{LiveEvent, LiveStream};
+use crate::metrics::METRICS;
+use crate::session::Sessions;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, warn};
+
+#[derive(Clone)]
+pub struct AppState {
+    pub health: Arc<Health>,
+    pub sessions: Sessions,
+    pub live: LiveStream,
+    pub stream_token: Option<String>,
+}
+
+pub async fn serve(listener: TcpListener, state: AppState) -> std::io::Result<()> {
+    let app = Router::new()
+        .route("/metrics", get(metrics))
+        .route("/healthz", get(healthz))
+        .route("/readyz", get(readyz))
+        .route("/stream", get(stream))
+        .with_state(state);
+    axum::serve(listener, app).await
+}
+
+async fn metrics() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        METRICS.encode(),
+    )
+}
+
+/// Liveness: the process is up and serving requests. Model state gates readiness instead.
+async fn healthz(State(state): State<AppState>) -> impl IntoResponse {
+    Json(json!({
+        "status": "ok",
+        "model_loaded": state.health.model_loaded(),
+    }))
+}
+
+/// Readiness: connected to the gateway and every voice driver is up.
+async fn readyz(State(state): State<AppState>) -> impl IntoResponse {
+    let gateway_connected = state.health.gateway_connected();
+    let model_loaded = state.health.model_loaded();
+    let disconnected_drivers = state.sessions.disconnected_drivers();
+    let ready = gateway_connected && model_loaded && disconnected_drivers == 0;
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (
+        status,
+        Json(json!({
+            "ready": ready,
+            "gateway_connected": gateway_connected,
+            "model_loaded": model_loaded,
+            "voice_sessions": state.sessions.count(),
+            "disconnected_drivers": disconnected_drivers,
+        })),
+    )
+}
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    token: Option<String>,
+    /// Comma-separated guild IDs to receive events for; all guilds when absent.
+    guild: Option<String>,
+    /// Comma-separated voice channel IDs to receive events for; all channels when absent.
+    channel: Option<String>,
+}
+
+struct Subscription {
+    guilds: Vec<String>,
+    channels: Vec<String>,
+}
+
+impl Subscription {
+    fn new(query: &StreamQuery) -> Self {
+        let ids = |list: &Option<String>| -> Vec<String> {
+            list.as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .map(String::from)
+                .collect()
+        };
+        Self {
+            guilds: ids(&query.guild),
+            channels: ids(&query.channel),
+        }
+    }
+
+    fn matches(&self, event: &LiveEvent) -> bool {
+        (self.guilds.is_empty() || self.guilds.contains(&event.guild_id))
+            && (self.channels.is_empty() || self.channels.contains(&event.channel_id))
+    }
+}
+
+/// Live transcript events over WebSocket.
+///
+/// The token goes in the `token` query parameter, since browser sources such as OBS can't
+/// set headers, or in an `Authorization: Bearer` header.
+async fn stream(
+    ws: WebSocketUpgrade,
+    Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Response {
+    let Some(expected) = state.stream_token.as_deref() else {
+        return (StatusCode::NOT_FOUND, "Live stream is disabled").into_response();
+    };
+
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let provided = query.token.as_deref().or(bearer).unwrap_or_default();
+    if !constant_time_eq(provided.as_bytes(), expected.as_bytes()) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let subscription = Subscription::new(&query);
+    let events = state.live.subscribe();
+    ws.on_upgrade(move |socket| forward_events(socket, events, subscription))
+}
+
+async fn forward_events(
+    mut socket: WebSocket,
+    mut events: broadcast::Receiver<Arc<LiveEvent>>,
+    subscription: Subscription,
+) {
+    debug!("Live stream client connected");
+
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    if !subscription.matches(&event) {
+                        continue;
+                    }
+                    let Ok(json) = serde_json::to_string(&*event) else {
+                        continue;
+                    };
+                    if socket.send(Message::Text(json)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    warn!(skipped, "Live stream client fell behind, skipped events");
+                }
+                Err(RecvError::Closed) => break,
+            },
+            // Clients only send pings and close frames; pings are answered automatically
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+
+    debug!("Live stream client disconnected");
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
@@ -0,0 +1,154 @@
+use crate::sink::{SessionContext, Transcript, TranscriptSink};
+use crate::translation::Translation;
+use serde::Serialize;
+use serenity::async_trait;
+use serenity::model::id::UserId;
+use serenity::prelude::TypeMapKey;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+// Events a slow subscriber can fall behind by before it starts missing some
+const EVENT_BUFFER: usize = 256;
+
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EventKind {
+    /// The utterance so far; superseded by later events with the same `started_at`.
+    Partial,
+    Final,
+}
+
+/// A transcript event as sent to WebSocket subscribers.
+///
+/// IDs are strings because Discord snowflakes don't fit in a JavaScript number.
+#[derive(Serialize)]
+pub struct LiveEvent {
+    #[serde(rename = "type")]
+    pub kind: EventKind,
+    pub guild_id: String,
+    pub channel_id: String,
+    pub session_id: u64,
+    pub ssrc: u32,
+    pub user_id: Option<String>,
+    pub text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_text: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub translations: Vec<Translation>,
+    /// Utterance start, in milliseconds since the Unix epoch.
+    pub started_at: u64,
+    /// When the event was produced, in milliseconds since the Unix epoch.
+    pub timestamp: u64,
+}
+
+impl LiveEvent {
+    pub fn partial(
+        session: &SessionContext,
+        ssrc: u32,
+        user_id: Option<UserId>,
+        text: String,
+        started_at: SystemTime,
+    ) -> Self {
+        Self {
+            kind: EventKind::Partial,
+            guild_id: session.guild_id.to_string(),
+            channel_id: session.channel_id.to_string(),
+            session_id: session.session_id,
+            ssrc,
+            user_id: user_id.map(|id| id.to_string()),
+            text,
+            raw_text: None,
+            translations: Vec::new(),
+            started_at: unix_millis(started_at),
+            timestamp: unix_millis(SystemTime::now()),
+        }
+    }
+
+    fn final_transcript(transcript: &Transcript, raw_text: bool) -> Self {
+        let session = &transcript.session;
+        Self {
+            kind: EventKind::Final,
+            guild_id: session.guild_id.to_string(),
+            channel_id: session.channel_id.to_string(),
+            session_id: session.session_id,
+            ssrc: transcript.ssrc,
+            user_id: transcript.user_id.map(|id| id.to_string()),
+            text: transcript.text.clone(),
+            raw_text: raw_text.then(|| transcript.raw_text.clone()),
+            translations: transcript.translations.clone(),
+            started_at: unix_millis(transcript.started_at),
+            timestamp: unix_millis(SystemTime::now()),
+        }
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+/// Fans transcript events out to WebSocket subscribers.
+#[derive(Clone)]
+pub struct LiveStream {
+    tx: broadcast::Sender<Arc<LiveEvent>>,
+}
+
+impl Default for LiveStream {
+    fn default() -> Self {
+        Self {
+            tx: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+}
+
+impl LiveStream {
+    /// Whether anyone is listening, so callers can skip building events nobody gets.
+    pub fn has_subscribers(&self) -> bool {
+        self.tx.receiver_count() > 0
+    }
+
+    pub fn publish(&self, event: LiveEvent) {
+        // Fails only when nobody is subscribed
+        let _ = self.tx.send(Arc::new(event));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<LiveEvent>> {
+        self.tx.subscribe()
+    }
+}
+
+/// Publishes final transcripts, after post-processing and translation, to the live stream.
+pub struct LiveStreamSink {
+    live: LiveStream,
+    /// Whether final events carry the unfiltered `raw_text`; not when the profanity filter
+    /// masks or removes words.
+    raw_text: bool,
+}
+
+impl LiveStreamSink {
+    pub fn new(live: LiveStream, raw_text: bool) -> Self {
+        Self { live, raw_text }
+    }
+}
+
+#[async_trait]
+impl TranscriptSink for LiveStreamSink {
+    fn name(&self) -> &'static str {
+        "live-stream"
+    }
+
+    async fn deliver(&self, transcript: &Transcript) -> Result<(), String> {
+        if self.live.has_subscribers() {
+            self.live
+                .publish(LiveEvent::final_transcript(transcript, self.raw_text));
+        }
+        Ok(())
+    }
+}
+
+pub struct LiveStreamKey;
+impl TypeMapKey for LiveStreamKey {
+    type Value = LiveStream;
+}
@@ -0,0 +1,20 @@
+use crate::config::LogFormat;
+use std::error::Error;
+use tracing_subscriber::EnvFilter;
+
+pub fn init(filter: &str, format: LogFormat) -> Result<(), Box<dyn Error>> {
+    let filter = EnvFilter::try_new(filter).map_err(|e| format!("Invalid log filter: {}", e))?;
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+    }
+    .map_err(|e| format!("Failed to initialize logging: {}", e))?;
+
+    Ok(())
+}
@@ -1,32 +1,210 @@
+mod agc;
+mod cli;
+mod commands;
 mod config;
+mod denoise;
 mod discord_bot;
+mod endpoint;
+mod guild_settings;
+mod health;
+mod history;
+mod http_server;
+mod live_stream;
+mod logging;
+mod metrics;
+mod mixer;
+mod permissions;
+mod pipeline;
+mod postprocess;
+mod recognizer_pool;
+mod session;
+mod sink;
+mod timeline;
 mod transcription;
+mod translation;
+mod tts;
 mod vosk_model;
 
-use config::Config;
-use discord_bot::{Handler, VoskModelKey};
+use clap::Parser;
+use cli::{Cli, Command, ModelsCommand};
+use config::{Config, ConfigKey};
+use discord_bot::Handler;
+use guild_settings::{GuildSettingsKey, GuildSettingsStore};
+use health::{Health, HealthKey};
+use history::{History, HistoryKey};
+use http_server::AppState;
+use live_stream::{LiveStream, LiveStreamKey};
+use recognizer_pool::{RecognizerPool, RecognizerPoolKey};
 use serenity::client::Client;
 use serenity::prelude::*;
-use songbird::SerenityInit;
+use session::{Sessions, SessionsKey};
+use songbird::driver::DecodeMode;
+use songbird::{SerenityInit, Songbird};
 use std::error::Error;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::{error, info, warn};
+use translation::{LibreTranslate, TranslationService, TranslationServiceKey};
+
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(15);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    let model_dir = cli.model_dir.unwrap_or_else(vosk_model::default_model_dir);
+
+    match cli.command.unwrap_or(Command::Run { model: None }) {
+        Command::Run { model } => {
+            let model = model.unwrap_or_else(|| vosk_model::DEFAULT_MODEL_NAME.to_string());
+            run(vosk_model::resolve(&model_dir, &model)).await
+        }
+        Command::Models(ModelsCommand::List) => vosk_model::list(&model_dir),
+        Command::Models(ModelsCommand::Install { source }) => {
+            vosk_model::install(&model_dir, &source)
+        }
+        Command::Models(ModelsCommand::Verify { model }) => vosk_model::verify(
+            &model_dir,
+            model.as_deref().unwrap_or(vosk_model::DEFAULT_MODEL_NAME),
+        ),
+        Command::Models(ModelsCommand::Remove { name }) => vosk_model::remove(&model_dir, &name),
+        Command::Bench {
+            pipeline: stages,
+            seconds,
+        } => pipeline::benchmark(&pipeline::parse_stages(&stages)?, seconds),
+    }
+}
+
+async fn run(model_path: PathBuf) -> Result<(), Box<dyn Error>> {
     let config = Config::from_env()?;
-    let model = vosk_model::load()?;
+    logging::init(&config.log_filter, config.log_format)?;
+
+    let health = Arc::new(Health::default());
+    let sessions = Sessions::default();
+    let live = LiveStream::default();
+
+    let listener = TcpListener::bind(config.http_addr).await?;
+    let state = AppState {
+        health: health.clone(),
+        sessions: sessions.clone(),
+        live: live.clone(),
+        stream_token: config.stream_token.clone(),
+    };
+    tokio::spawn(async move {
+        if let Err(e) = http_server::serve(listener, state).await {
+            error!("HTTP server error: {}", e);
+        }
+    });
+    info!(addr = %config.http_addr, "HTTP server listening");
+
+    let model = vosk_model::load(&model_path)?;
+    health.set_model_loaded(true);
+    info!(path = %model_path.display(), "Vosk model loaded");
+    let model_name = model_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| model_path.display().to_string());
+    let pool = RecognizerPool::new(
+        model,
+        model_name,
+        config.max_recognizers,
+        config.recognizer_pool_size,
+    );
+
+    let guild_settings = GuildSettingsStore::load(config.guild_settings_path.clone())?;
+    let history = History::open(&config.history_db)?;
+    history.spawn_retention(guild_settings.clone(), config.history_retention_days);
+    let translation = config.translate_url.as_deref().map(|url| {
+        info!(url, "Translation enabled");
+        TranslationService::new(Box::new(LibreTranslate::new(
+            url,
+            config.translate_api_key.clone(),
+        )))
+    });
 
     let intents = GatewayIntents::GUILDS
         | GatewayIntents::GUILD_VOICE_STATES
         | GatewayIntents::GUILD_MESSAGES
         | GatewayIntents::MESSAGE_CONTENT;
 
+    let voice =
+        Songbird::serenity_from_config(songbird::Config::default().decode_mode(DecodeMode::Decode));
+
     let mut client = Client::builder(&config.discord_token, intents)
         .event_handler(Handler)
-        .register_songbird()
+        .register_songbird_with(voice.clone())
         .await?;
 
-    client.data.write().await.insert::<VoskModelKey>(model);
-    client.start().await?;
+    {
+        let mut data = client.data.write().await;
+        data.insert::<RecognizerPoolKey>(pool);
+        data.insert::<HealthKey>(health);
+        data.insert::<SessionsKey>(sessions.clone());
+        data.insert::<GuildSettingsKey>(guild_settings);
+        data.insert::<LiveStreamKey>(live);
+        data.insert::<HistoryKey>(history);
+        if let Some(translation) = translation {
+            data.insert::<TranslationServiceKey>(translation);
+        }
+        data.insert::<ConfigKey>(Arc::new(config));
+    }
+
+    let shard_manager = client.shard_manager.clone();
+
+    tokio::select! {
+        result = client.start() => result?,
+        _ = shutdown_signal() => {
+            info!("Shutdown requested, flushing transcripts");
+            if tokio::time::timeout(SHUTDOWN_TIMEOUT, close_sessions(&sessions, &voice))
+                .await
+                .is_err()
+            {
+                warn!("Timed out flushing transcripts, shutting down anyway");
+            }
+            shard_manager.shutdown_all().await;
+            info!("Shutdown complete");
+        }
+    }
 
     Ok(())
 }
+
+/// Finalizes and flushes every active session, then leaves its voice channel.
+async fn close_sessions(sessions: &Sessions, voice: &Songbird) {
+    for (guild_id, session) in sessions.drain() {
+        session.close().await;
+        if let Err(e) = voice.remove(guild_id).await {
+            warn!(
+                guild_id = guild_id.get(),
+                "Failed to leave voice channel: {:?}", e
+            );
+        }
+    }
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = sigterm.recv() => {}
+                }
+            }
+            Err(e) => {
+                warn!("Failed to install SIGTERM handler: {}", e);
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
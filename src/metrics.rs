@@ -0,0 +1,266 @@
+use prometheus::{
+    Counter, Encoder, Histogram, HistogramOpts, IntCounter, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::LazyLock;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    pub active_sessions: IntGauge,
+    pub tracked_ssrcs: IntGauge,
+    pub utterances: IntCounter,
+    pub utterance_splits: IntCounter,
+    pub words: IntCounter,
+    pub dropped_frames: IntCounter,
+    pub lost_packets: IntCounter,
+    pub gap_silence_seconds: Counter,
+    pub recognizer_failures: IntCounter,
+    pub recognizer_cap_rejections: IntCounter,
+    pub active_recognizers: IntGauge,
+    pub idle_recognizers: IntGauge,
+    pub evicted_speakers: IntCounter,
+    pub translation_cache_hits: IntCounter,
+    pub translation_failures: IntCounter,
+    pub accept_waveform_seconds: Histogram,
+    pub real_time_factor: Histogram,
+    pub denoise_seconds: Histogram,
+    pub agc_gain: Histogram,
+    pub transcript_latency_seconds: Histogram,
+    pub end_of_utterance_timeout_seconds: Histogram,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("voicebot".into()), None).expect("valid metrics namespace");
+
+        let active_sessions = IntGauge::with_opts(Opts::new(
+            "active_voice_sessions",
+            "Voice channels the bot is currently transcribing",
+        ))
+        .unwrap();
+        let tracked_ssrcs = IntGauge::with_opts(Opts::new(
+            "tracked_ssrcs",
+            "Speakers (SSRCs) with live recognizer state",
+        ))
+        .unwrap();
+        let utterances = IntCounter::with_opts(Opts::new(
+            "utterances_total",
+            "Finalized non-empty transcriptions",
+        ))
+        .unwrap();
+        let utterance_splits = IntCounter::with_opts(Opts::new(
+            "utterance_splits_total",
+            "Utterances split for reaching the maximum utterance length",
+        ))
+        .unwrap();
+        let words = IntCounter::with_opts(Opts::new(
+            "words_total",
+            "Words in finalized transcriptions",
+        ))
+        .unwrap();
+        let dropped_frames = IntCounter::with_opts(Opts::new(
+            "dropped_frames_total",
+            "Voice frames that could not be fed to a recognizer",
+        ))
+        .unwrap();
+        let lost_packets = IntCounter::with_opts(Opts::new(
+            "lost_packets_total",
+            "RTP packets missing from speakers' streams, by sequence number",
+        ))
+        .unwrap();
+        let gap_silence_seconds = Counter::with_opts(Opts::new(
+            "gap_silence_seconds_total",
+            "Silence inserted into speakers' streams for lost or missing audio",
+        ))
+        .unwrap();
+        let recognizer_failures = IntCounter::with_opts(Opts::new(
+            "recognizer_creation_failures_total",
+            "Failed attempts to create a Vosk recognizer",
+        ))
+        .unwrap();
+        let recognizer_cap_rejections = IntCounter::with_opts(Opts::new(
+            "recognizer_cap_rejections_total",
+            "Speakers refused a recognizer because the concurrent recognizer cap was reached, once per stretch of speech",
+        ))
+        .unwrap();
+        let active_recognizers = IntGauge::with_opts(Opts::new(
+            "active_recognizers",
+            "Recognizers currently assigned to speakers",
+        ))
+        .unwrap();
+        let idle_recognizers = IntGauge::with_opts(Opts::new(
+            "idle_recognizers",
+            "Reset recognizers waiting in the pool for reuse",
+        ))
+        .unwrap();
+        let evicted_speakers = IntCounter::with_opts(Opts::new(
+            "evicted_speakers_total",
+            "Speaker states dropped after being idle or to free a recognizer",
+        ))
+        .unwrap();
+        let translation_cache_hits = IntCounter::with_opts(Opts::new(
+            "translation_cache_hits_total",
+            "Translations served from the cache",
+        ))
+        .unwrap();
+        let translation_failures = IntCounter::with_opts(Opts::new(
+            "translation_failures_total",
+            "Translations that failed and fell back to the original text",
+        ))
+        .unwrap();
+        let accept_waveform_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "accept_waveform_seconds",
+                "Time spent in Recognizer::accept_waveform per buffer",
+            )
+            .buckets(vec![
+                0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5,
+            ]),
+        )
+        .unwrap();
+        let real_time_factor = Histogram::with_opts(
+            HistogramOpts::new(
+                "real_time_factor",
+                "Recognition time divided by audio duration per buffer",
+            )
+            .buckets(vec![0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.0]),
+        )
+        .unwrap();
+        let denoise_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "denoise_seconds",
+                "Time spent suppressing noise per voice frame",
+            )
+            .buckets(vec![0.0001, 0.00025, 0.0005, 0.001, 0.0025, 0.005, 0.01]),
+        )
+        .unwrap();
+        let agc_gain = Histogram::with_opts(
+            HistogramOpts::new(
+                "agc_gain",
+                "Gain applied to speakers' audio by automatic gain control, per block",
+            )
+            .buckets(vec![0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 4.0, 6.0, 10.0]),
+        )
+        .unwrap();
+        let transcript_latency_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "transcript_latency_seconds",
+                "Time from the last received audio of an utterance to its final transcript",
+            )
+            .buckets(vec![0.05, 0.1, 0.25, 0.5, 1.0, 1.5, 2.0, 3.0, 5.0]),
+        )
+        .unwrap();
+        let end_of_utterance_timeout_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "end_of_utterance_timeout_seconds",
+                "Silence that ended utterances, as learned per speaker",
+            )
+            .buckets(vec![0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 3.0, 5.0]),
+        )
+        .unwrap();
+
+        registry
+            .register(Box::new(active_sessions.clone()))
+            .unwrap();
+        registry.register(Box::new(tracked_ssrcs.clone())).unwrap();
+        registry.register(Box::new(utterances.clone())).unwrap();
+        registry
+            .register(Box::new(utterance_splits.clone()))
+            .unwrap();
+        registry.register(Box::new(words.clone())).unwrap();
+        registry.register(Box::new(dropped_frames.clone())).unwrap();
+        registry.register(Box::new(lost_packets.clone())).unwrap();
+        registry
+            .register(Box::new(gap_silence_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(recognizer_failures.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(recognizer_cap_rejections.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(active_recognizers.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(idle_recognizers.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(evicted_speakers.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(translation_cache_hits.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(translation_failures.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(accept_waveform_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(real_time_factor.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(denoise_seconds.clone()))
+            .unwrap();
+        registry.register(Box::new(agc_gain.clone())).unwrap();
+        registry
+            .register(Box::new(transcript_latency_seconds.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(end_of_utterance_timeout_seconds.clone()))
+            .unwrap();
+
+        Self {
+            registry,
+            active_sessions,
+            tracked_ssrcs,
+            utterances,
+            utterance_splits,
+            words,
+            dropped_frames,
+            lost_packets,
+            gap_silence_seconds,
+            recognizer_failures,
+            recognizer_cap_rejections,
+            active_recognizers,
+            idle_recognizers,
+            evicted_speakers,
+            translation_cache_hits,
+            translation_failures,
+            accept_waveform_seconds,
+            real_time_factor,
+            denoise_seconds,
+            agc_gain,
+            transcript_latency_seconds,
+            end_of_utterance_timeout_seconds,
+        }
+    }
+
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        let encoder = TextEncoder::new();
+        if let Err(e) = encoder.encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Error encoding metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Keeps `active_voice_sessions` in sync with the lifetime of a `Receiver`.
+pub struct SessionGauge;
+
+impl SessionGauge {
+    pub fn start() -> Self {
+        METRICS.active_sessions.inc();
+        Self
+    }
+}
+
+impl Drop for SessionGauge {
+    fn drop(&mut self) {
+        METRICS.active_sessions.dec();
+    }
+}
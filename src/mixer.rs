@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+/// Sums speakers' 48 kHz stereo frames into one stream, clipping at the i16 range.
+pub fn mix<'a>(frames: impl IntoIterator<Item = &'a [i16]>) -> Vec<i16> {
+    let mut mixed: Vec<i32> = Vec::new();
+    for frame in frames {
+        if mixed.len() < frame.len() {
+            mixed.resize(frame.len(), 0);
+        }
+        for (sum, sample) in mixed.iter_mut().zip(frame) {
+            *sum += i32::from(*sample);
+        }
+    }
+    mixed
+        .into_iter()
+        .map(|sum| sum.clamp(i32::from(i16::MIN), i32::from(i16::MAX)) as i16)
+        .collect()
+}
+
+/// Text recognized from the mixed stream and the speaker it was attributed to.
+pub struct Segment {
+    pub ssrc: u32,
+    pub text: String,
+    /// Session timeline frame the segment's audio started at.
+    pub start: u64,
+}
+
+/// Attributes text recognized from a mixed stream to whoever was loudest while it was said.
+///
+/// Energy is summed per speaker between pieces of recognized text. Consecutive pieces from
+/// the same speaker are merged into one segment.
+#[derive(Default)]
+pub struct Attribution {
+    energy: HashMap<u32, f64>,
+    chunk_start: Option<u64>,
+    segments: Vec<Segment>,
+}
+
+impl Attribution {
+    /// Adds a speaker's share of the audio at timeline frame `position`.
+    pub fn add_energy(&mut self, ssrc: u32, samples: &[i16], position: u64) {
+        let energy: f64 = samples
+            .iter()
+            .map(|sample| f64::from(*sample).powi(2))
+            .sum();
+        *self.energy.entry(ssrc).or_default() += energy;
+        self.chunk_start.get_or_insert(position);
+    }
+
+    /// The segment being spoken, for partial results.
+    pub fn current_segment(&self) -> Option<&Segment> {
+        self.segments.last()
+    }
+
+    /// Assigns newly recognized text to the loudest speaker since the previous text.
+    pub fn attribute(&mut self, text: &str) {
+        let start = self.chunk_start.take().unwrap_or_default();
+        let dominant = self.dominant();
+        self.energy.clear();
+        if text.is_empty() {
+            return;
+        }
+
+        // Text without any audio energy (e.g. flushed at the end) goes to the last speaker
+        let Some(ssrc) = dominant.or_else(|| self.segments.last().map(|segment| segment.ssrc))
+        else {
+            return;
+        };
+        match self.segments.last_mut() {
+            Some(last) if last.ssrc == ssrc => {
+                last.text.push(' ');
+                last.text.push_str(text);
+            }
+            _ => self.segments.push(Segment {
+                ssrc,
+                text: text.to_string(),
+                start,
+            }),
+        }
+    }
+
+    pub fn take_segments(&mut self) -> Vec<Segment> {
+        self.energy.clear();
+        self.chunk_start = None;
+        std::mem::take(&mut self.segments)
+    }
+
+    fn dominant(&self) -> Option<u32> {
+        self.energy
+            .iter()
+            .filter(|(_, energy)| **energy > 0.0)
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(ssrc, _)| *ssrc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOUD: [i16; 4] = [8000; 4];
+    const QUIET: [i16; 4] = [100; 4];
+
+    #[test]
+    fn mix_sums_and_clips() {
+        let frames: [&[i16]; 2] = [&[1000, i16::MAX], &[-500, 1000, 7]];
+        assert_eq!(mix(frames), [500, i16::MAX, 7]);
+        assert!(mix(std::iter::empty()).is_empty());
+    }
+
+    #[test]
+    fn attributes_text_to_the_loudest_speaker() {
+        let mut attribution = Attribution::default();
+        attribution.add_energy(1, &LOUD, 0);
+        attribution.add_energy(2, &QUIET, 0);
+        attribution.attribute("hello");
+        attribution.add_energy(1, &LOUD, 960);
+        attribution.attribute("there");
+
+        attribution.add_energy(1, &QUIET, 1920);
+        attribution.add_energy(2, &LOUD, 1920);
+        attribution.add_energy(2, &LOUD, 2880);
+        attribution.attribute("hi");
+        assert_eq!(attribution.current_segment().map(|s| s.ssrc), Some(2));
+
+        // No energy since the last text, e.g. flushed at the end
+        attribution.attribute("bye");
+
+        let segments: Vec<(u32, String, u64)> = attribution
+            .take_segments()
+            .into_iter()
+            .map(|segment| (segment.ssrc, segment.text, segment.start))
+            .collect();
+        assert_eq!(
+            segments,
+            [
+                (1, String::from("hello there"), 0),
+                (2, String::from("hi bye"), 1920)
+            ]
+        );
+        assert!(attribution.current_segment().is_none());
+    }
+
+    #[test]
+    fn drops_text_without_any_speaker() {
+        let mut attribution = Attribution::default();
+        attribution.add_energy(1, &[0; 4], 0);
+        attribution.attribute("hello");
+        assert!(attribution.take_segments().is_empty());
+    }
+
+    #[test]
+    fn empty_text_resets_the_chunk() {
+        let mut attribution = Attribution::default();
+        attribution.add_energy(1, &LOUD, 0);
+        attribution.attribute("");
+        attribution.add_energy(2, &LOUD, 960);
+        attribution.attribute("hi");
+
+        let segments = attribution.take_segments();
+        assert_eq!(segments.len(), 1);
+        assert_eq!((segments[0].ssrc, segments[0].start), (2, 960));
+    }
+}
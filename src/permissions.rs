@@ -0,0 +1,245 @@
+use crate::commands::Invocation;
+use crate::guild_settings::GuildSettings;
+use serde::{Deserialize, Serialize};
+use serenity::client::Context;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::model::Permissions;
+
+/// Roles and users allowed to run a command in a guild.
+///
+/// When either list is non-empty it replaces the command's default Discord permission check.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandAccess {
+    pub roles: Vec<u64>,
+    pub users: Vec<u64>,
+}
+
+impl CommandAccess {
+    pub fn is_restricted(&self) -> bool {
+        !self.roles.is_empty() || !self.users.is_empty()
+    }
+}
+
+/// The member invoking a command.
+pub struct Caller {
+    pub user_id: UserId,
+    pub roles: Vec<RoleId>,
+    pub permissions: Permissions,
+    pub voice_channel: Option<ChannelId>,
+}
+
+impl Caller {
+    pub async fn fetch(ctx: &Context, guild_id: GuildId, user_id: UserId) -> Result<Self, String> {
+        let member = guild_id
+            .member(ctx, user_id)
+            .await
+            .map_err(|e| format!("failed to look up member: {}", e))?;
+
+        let guild = ctx
+            .cache
+            .guild(guild_id)
+            .ok_or("server is not cached yet")?;
+        // Guild-wide permissions; commands aren't tied to a channel's overwrites
+        #[allow(deprecated)]
+        let permissions = guild.member_permissions(&member);
+        let voice_channel = guild
+            .voice_states
+            .get(&user_id)
+            .and_then(|state| state.channel_id);
+
+        Ok(Self {
+            user_id,
+            roles: member.roles.clone(),
+            permissions,
+            voice_channel,
+        })
+    }
+}
+
+/// Discord permissions a command needs when the guild hasn't restricted it to roles or users.
+///
+/// Viewing settings is open to everyone; changing them needs Manage Server. Sending the bot
+/// to a named channel rather than the caller's needs Move Members, and linking a channel for
+/// read-aloud needs Manage Channels.
+fn required_permissions(command: &str, args: &[&str]) -> Permissions {
+    match (command, args) {
+        ("join", [_, ..]) => Permissions::MOVE_MEMBERS,
+        (
+            "postprocess" | "profanity" | "translate" | "permissions" | "prefix" | "mode"
+            | "denoise" | "endpoint",
+            [_, ..],
+        ) => Permissions::MANAGE_GUILD,
+        ("history", ["retention", ..]) => Permissions::MANAGE_GUILD,
+        ("tts", [_, ..]) => Permissions::MANAGE_CHANNELS,
+        _ => Permissions::empty(),
+    }
+}
+
+/// Checks whether `caller` may run the invoked command, returning a denial message if not.
+///
+/// `session_channel` is the voice channel the bot is transcribing in this guild, if any.
+/// Moving the bot away from or out of a channel needs Move Members unless the caller is in it.
+pub fn check(
+    settings: &GuildSettings,
+    invocation: &Invocation,
+    caller: &Caller,
+    session_channel: Option<ChannelId>,
+) -> Result<(), String> {
+    if caller.permissions.administrator() {
+        return Ok(());
+    }
+
+    let command = invocation.command.name;
+    let prefix = &invocation.prefix;
+
+    if let Some(access) = settings
+        .command_permissions
+        .get(command)
+        .filter(|access| access.is_restricted())
+    {
+        let allowed = access.users.contains(&caller.user_id.get())
+            || caller
+                .roles
+                .iter()
+                .any(|role| access.roles.contains(&role.get()));
+        return if allowed {
+            Ok(())
+        } else {
+            Err(format!(
+                "🚫 `{}{}` is limited to specific roles or users on this server",
+                prefix, command
+            ))
+        };
+    }
+
+    let mut required = required_permissions(command, &invocation.args());
+    let takes_over_session = matches!(command, "join" | "leave")
+        && session_channel.is_some()
+        && caller.voice_channel != session_channel;
+    if takes_over_session {
+        required |= Permissions::MOVE_MEMBERS;
+    }
+
+    let mut missing = required - caller.permissions;
+    // Manage Server covers the channel settings Manage Channels allows
+    if caller.permissions.manage_guild() {
+        missing -= Permissions::MANAGE_CHANNELS;
+    }
+    if missing.is_empty() {
+        Ok(())
+    } else if takes_over_session {
+        Err(format!(
+            "🚫 The bot is transcribing another voice channel; `{}{}` needs you to be in that \
+             channel or have {}",
+            prefix,
+            command,
+            describe_permissions(missing)
+        ))
+    } else {
+        Err(format!(
+            "🚫 `{}{}` needs {}",
+            prefix,
+            command,
+            describe_permissions(missing)
+        ))
+    }
+}
+
+fn describe_permissions(permissions: Permissions) -> String {
+    let names = permissions.get_permission_names();
+    format!("the {} permission", names.join(", "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROLE: RoleId = RoleId::new(7);
+    const VOICE: ChannelId = ChannelId::new(20);
+
+    fn invocation(content: &str) -> Invocation {
+        Invocation::parse(content, "!", UserId::new(1)).unwrap()
+    }
+
+    fn caller(permissions: Permissions) -> Caller {
+        Caller {
+            user_id: UserId::new(2),
+            roles: vec![ROLE],
+            permissions,
+            voice_channel: None,
+        }
+    }
+
+    fn restricted(command: &str, access: CommandAccess) -> GuildSettings {
+        let mut settings = GuildSettings::default();
+        settings
+            .command_permissions
+            .insert(command.to_string(), access);
+        settings
+    }
+
+    #[test]
+    fn owner_may_run_anything() {
+        // Serenity grants the guild owner every permission
+        let owner = caller(Permissions::all());
+        let settings = restricted("prefix", CommandAccess::default());
+        assert!(check(&settings, &invocation("!prefix ?"), &owner, Some(VOICE)).is_ok());
+        assert!(check(&settings, &invocation("!leave"), &owner, Some(VOICE)).is_ok());
+    }
+
+    #[test]
+    fn administrators_bypass_restrictions() {
+        let admin = caller(Permissions::ADMINISTRATOR);
+        let settings = restricted(
+            "mode",
+            CommandAccess {
+                roles: vec![99],
+                users: Vec::new(),
+            },
+        );
+        assert!(check(&settings, &invocation("!mode mixed"), &admin, None).is_ok());
+    }
+
+    #[test]
+    fn role_or_permission_grants_access() {
+        let member = caller(Permissions::empty());
+        let settings = restricted(
+            "mode",
+            CommandAccess {
+                roles: vec![ROLE.get()],
+                users: Vec::new(),
+            },
+        );
+        assert!(check(&settings, &invocation("!mode mixed"), &member, None).is_ok());
+
+        let manager = caller(Permissions::MANAGE_GUILD);
+        let settings = GuildSettings::default();
+        assert!(check(&settings, &invocation("!prefix ?"), &manager, None).is_ok());
+        // Manage Server covers Manage Channels
+        assert!(check(&settings, &invocation("!tts #general"), &manager, None).is_ok());
+    }
+
+    #[test]
+    fn denies_callers_without_access() {
+        let member = caller(Permissions::SEND_MESSAGES);
+        let settings = GuildSettings::default();
+        assert!(check(&settings, &invocation("!prefix"), &member, None).is_ok());
+        assert_eq!(
+            check(&settings, &invocation("!prefix ?"), &member, None),
+            Err(String::from(
+                "🚫 `!prefix` needs the Manage Guilds permission"
+            ))
+        );
+        assert!(check(&settings, &invocation("!leave"), &member, Some(VOICE)).is_err());
+
+        let settings = restricted(
+            "search",
+            CommandAccess {
+                roles: vec![99],
+                users: vec![3],
+            },
+        );
+        assert!(check(&settings, &invocation("!search hello"), &member, None).is_err());
+    }
+}
@@ -0,0 +1,497 @@
+use crate::agc::Agc;
+use crate::denoise::Denoiser;
+use crate::recognizer_pool::TARGET_SAMPLE_RATE;
+use crate::timeline::{FRAMES_PER_TICK, SAMPLE_RATE};
+use std::error::Error;
+use std::f32::consts::PI;
+use std::time::{Duration, Instant};
+
+/// Stages run when `AUDIO_PIPELINE` isn't set.
+pub const DEFAULT_STAGES: &str = "downmix,denoise,resample,agc,buffer";
+/// Audio the buffer stage collects before passing it on.
+const BUFFER_SECS: f32 = 0.1;
+/// Blocks quieter than this (-44 dBFS RMS) aren't speech to the VAD stage.
+const VAD_THRESHOLD_RMS: f32 = 200.0;
+/// How long the VAD stage keeps passing audio after speech, so word endings aren't cut.
+const VAD_HANGOVER_SECS: f32 = 0.3;
+
+/// Sample rate and channel count of the interleaved audio between two stages.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioFormat {
+    pub sample_rate: u32,
+    pub channels: usize,
+}
+
+impl AudioFormat {
+    /// What songbird decodes voice packets to.
+    pub const VOICE: Self = Self {
+        sample_rate: SAMPLE_RATE as u32,
+        channels: 2,
+    };
+    /// What Vosk recognizers are created for.
+    pub const RECOGNIZER: Self = Self {
+        sample_rate: TARGET_SAMPLE_RATE as u32,
+        channels: 1,
+    };
+
+    /// Interleaved samples in `secs` of audio.
+    pub fn samples(self, secs: f32) -> usize {
+        (secs * self.sample_rate as f32) as usize * self.channels
+    }
+}
+
+/// One step between a speaker's decoded voice and their recognizer.
+pub trait AudioStage: Send {
+    /// Transforms interleaved samples. A stage may hold samples back, e.g. until it has a
+    /// full frame, and return fewer than it was given.
+    fn process(&mut self, samples: Vec<i16>) -> Vec<i16>;
+
+    /// Returns any samples held back, at the end of an utterance.
+    fn flush(&mut self) -> Vec<i16> {
+        Vec::new()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StageKind {
+    /// Averages all channels into one.
+    Downmix,
+    /// Decimates to the recognizer's 16 kHz.
+    Resample,
+    /// RNNoise noise suppression; 48 kHz only, and skipped unless the guild enabled it.
+    Denoise,
+    /// Automatic gain control and peak limiter.
+    Agc,
+    /// Energy-based voice activity detection that silences audio between speech.
+    Vad,
+    /// Collects 100 ms of audio before passing it on, so the recognizer runs less often.
+    Buffer,
+}
+
+impl StageKind {
+    pub const NAMES: &'static [&'static str] =
+        &["downmix", "resample", "denoise", "agc", "vad", "buffer"];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "downmix" => Some(Self::Downmix),
+            "resample" => Some(Self::Resample),
+            "denoise" => Some(Self::Denoise),
+            "agc" => Some(Self::Agc),
+            "vad" => Some(Self::Vad),
+            "buffer" => Some(Self::Buffer),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Downmix => "downmix",
+            Self::Resample => "resample",
+            Self::Denoise => "denoise",
+            Self::Agc => "agc",
+            Self::Vad => "vad",
+            Self::Buffer => "buffer",
+        }
+    }
+
+    /// Creates the stage for `input`, returning it with the format it produces.
+    fn build(self, input: AudioFormat) -> Result<(Box<dyn AudioStage>, AudioFormat), String> {
+        let stage: (Box<dyn AudioStage>, AudioFormat) = match self {
+            Self::Downmix if input.channels == 1 => return Err("audio is already mono".into()),
+            Self::Downmix => (
+                Box::new(Downmix {
+                    channels: input.channels,
+                }),
+                AudioFormat {
+                    channels: 1,
+                    ..input
+                },
+            ),
+            Self::Resample => {
+                let target = AudioFormat::RECOGNIZER.sample_rate;
+                if input.sample_rate == target || !input.sample_rate.is_multiple_of(target) {
+                    return Err(format!(
+                        "can't resample {} Hz to {} Hz",
+                        input.sample_rate, target
+                    ));
+                }
+                (
+                    Box::new(Resample {
+                        factor: (input.sample_rate / target) as usize,
+                        channels: input.channels,
+                        phase: 0,
+                    }),
+                    AudioFormat {
+                        sample_rate: target,
+                        ..input
+                    },
+                )
+            }
+            Self::Denoise if input.sample_rate != SAMPLE_RATE as u32 => {
+                return Err(format!(
+                    "needs {} Hz audio, not {} Hz",
+                    SAMPLE_RATE, input.sample_rate
+                ))
+            }
+            Self::Denoise => (Box::new(Denoiser::new(input.channels)), input),
+            Self::Agc => (Box::new(Agc::new(input)), input),
+            Self::Vad => (
+                Box::new(Vad {
+                    hangover: input.samples(VAD_HANGOVER_SECS),
+                    remaining: 0,
+                }),
+                input,
+            ),
+            Self::Buffer => (
+                Box::new(Buffer {
+                    size: input.samples(BUFFER_SECS),
+                    pending: Vec::new(),
+                }),
+                input,
+            ),
+        };
+        Ok(stage)
+    }
+}
+
+/// Parses a comma-separated list of stage names, e.g. `AUDIO_PIPELINE`, and checks that the
+/// stages fit together.
+pub fn parse_stages(list: &str) -> Result<Vec<StageKind>, String> {
+    let stages = list
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            StageKind::parse(name).ok_or_else(|| {
+                format!(
+                    "unknown audio stage `{}` (stages: {})",
+                    name,
+                    StageKind::NAMES.join(", ")
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Pipeline::build(&stages)?;
+    Ok(stages)
+}
+
+/// A speaker's audio stages, from 48 kHz stereo voice to 16 kHz mono recognizer input.
+pub struct Pipeline {
+    stages: Vec<(StageKind, Box<dyn AudioStage>)>,
+}
+
+impl Pipeline {
+    pub fn build(stages: &[StageKind]) -> Result<Self, String> {
+        let mut format = AudioFormat::VOICE;
+        let mut built = Vec::with_capacity(stages.len());
+        for kind in stages {
+            let (stage, output) = kind
+                .build(format)
+                .map_err(|e| format!("{} stage: {}", kind.name(), e))?;
+            built.push((*kind, stage));
+            format = output;
+        }
+
+        if format != AudioFormat::RECOGNIZER {
+            return Err(format!(
+                "pipeline produces {} Hz audio with {} channel(s), but the recognizer needs {} Hz mono",
+                format.sample_rate,
+                format.channels,
+                AudioFormat::RECOGNIZER.sample_rate
+            ));
+        }
+        Ok(Self { stages: built })
+    }
+
+    pub fn process(&mut self, samples: Vec<i16>) -> Vec<i16> {
+        self.stages.iter_mut().fold(samples, |samples, (_, stage)| {
+            if samples.is_empty() {
+                samples
+            } else {
+                stage.process(samples)
+            }
+        })
+    }
+
+    /// Drains every stage in order, so audio held back early on still passes through the rest.
+    pub fn flush(&mut self) -> Vec<i16> {
+        let mut samples = Vec::new();
+        for (_, stage) in &mut self.stages {
+            if !samples.is_empty() {
+                samples = stage.process(samples);
+            }
+            samples.extend(stage.flush());
+        }
+        samples
+    }
+}
+
+struct Downmix {
+    channels: usize,
+}
+
+impl AudioStage for Downmix {
+    fn process(&mut self, samples: Vec<i16>) -> Vec<i16> {
+        samples
+            .chunks_exact(self.channels)
+            .map(|frame| {
+                let sum: i32 = frame.iter().map(|sample| i32::from(*sample)).sum();
+                (sum / self.channels as i32) as i16
+            })
+            .collect()
+    }
+}
+
+/// Keeps every `factor`th frame.
+struct Resample {
+    factor: usize,
+    channels: usize,
+    /// Frames to skip before the next kept one, carried across calls.
+    phase: usize,
+}
+
+impl AudioStage for Resample {
+    fn process(&mut self, samples: Vec<i16>) -> Vec<i16> {
+        let frames = samples.len() / self.channels;
+        let kept: Vec<i16> = samples
+            .chunks_exact(self.channels)
+            .skip(self.phase)
+            .step_by(self.factor)
+            .flatten()
+            .copied()
+            .collect();
+        let kept_frames = kept.len() / self.channels;
+        self.phase = (self.phase + kept_frames * self.factor).saturating_sub(frames);
+        kept
+    }
+}
+
+struct Vad {
+    hangover: usize,
+    /// Samples still passed since the last speech.
+    remaining: usize,
+}
+
+impl AudioStage for Vad {
+    fn process(&mut self, mut samples: Vec<i16>) -> Vec<i16> {
+        if rms(&samples) >= VAD_THRESHOLD_RMS {
+            self.remaining = self.hangover;
+        } else if self.remaining > 0 {
+            self.remaining = self.remaining.saturating_sub(samples.len());
+        } else {
+            samples.fill(0);
+        }
+        samples
+    }
+}
+
+struct Buffer {
+    size: usize,
+    pending: Vec<i16>,
+}
+
+impl AudioStage for Buffer {
+    fn process(&mut self, samples: Vec<i16>) -> Vec<i16> {
+        self.pending.extend_from_slice(&samples);
+        if self.pending.len() >= self.size {
+            std::mem::take(&mut self.pending)
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn flush(&mut self) -> Vec<i16> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+pub fn rms(samples: &[i16]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = samples
+        .iter()
+        .map(|sample| f64::from(*sample).powi(2))
+        .sum();
+    (sum / samples.len() as f64).sqrt() as f32
+}
+
+/// Runs `seconds` of generated audio through one speaker's pipeline and prints the CPU cost of
+/// each stage.
+pub fn benchmark(stages: &[StageKind], seconds: u64) -> Result<(), Box<dyn Error>> {
+    if seconds == 0 {
+        return Err("the benchmark needs at least one second of audio".into());
+    }
+    let mut pipeline = Pipeline::build(stages)?;
+
+    // A tone under white noise; a fixed LCG keeps runs comparable without an RNG crate
+    let mut seed: u32 = 1;
+    let second: Vec<i16> = (0..SAMPLE_RATE)
+        .flat_map(|i| {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            let noise = f32::from((seed >> 16) as u16 as i16) * 0.1;
+            let tone = (i as f32 * 2.0 * PI * 220.0 / SAMPLE_RATE as f32).sin() * 8000.0;
+            let sample = (tone + noise).clamp(f32::from(i16::MIN), f32::from(i16::MAX)) as i16;
+            [sample, sample]
+        })
+        .collect();
+
+    let mut elapsed = vec![Duration::ZERO; pipeline.stages.len()];
+    for _ in 0..seconds {
+        for tick in second.chunks(FRAMES_PER_TICK as usize * 2) {
+            let mut samples = tick.to_vec();
+            for ((_, stage), elapsed) in pipeline.stages.iter_mut().zip(&mut elapsed) {
+                if samples.is_empty() {
+                    break;
+                }
+                let started = Instant::now();
+                samples = std::hint::black_box(stage.process(samples));
+                *elapsed += started.elapsed();
+            }
+        }
+    }
+
+    println!("Processed {}s of 48 kHz stereo audio per stage:", seconds);
+    let total: Duration = elapsed.iter().sum();
+    let stage_times = pipeline.stages.iter().map(|(kind, _)| kind.name());
+    for (name, elapsed) in stage_times
+        .chain(["total"])
+        .zip(elapsed.iter().chain([&total]))
+    {
+        let real_time_factor = elapsed.as_secs_f64() / seconds as f64;
+        println!(
+            "  {:<10} {:>10.2?}  real-time factor {:.5} ({:.2}% of one core per speaker)",
+            name,
+            elapsed,
+            real_time_factor,
+            real_time_factor * 100.0
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(size: usize) -> Buffer {
+        Buffer {
+            size,
+            pending: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn resample_carries_phase_across_uneven_chunks() {
+        let mut resample = Resample {
+            factor: 3,
+            channels: 1,
+            phase: 0,
+        };
+        let input: Vec<i16> = (0..100).collect();
+        let mut output = Vec::new();
+        let mut rest = input.as_slice();
+        for size in [7, 5, 11, 1, 1, 75] {
+            let (chunk, remaining) = rest.split_at(size);
+            output.extend(resample.process(chunk.to_vec()));
+            rest = remaining;
+        }
+
+        let expected: Vec<i16> = (0..100).step_by(3).collect();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn resample_keeps_frames_together() {
+        let mut resample = Resample {
+            factor: 2,
+            channels: 2,
+            phase: 0,
+        };
+        assert_eq!(resample.process(vec![1, -1, 2, -2, 3, -3]), [1, -1, 3, -3]);
+        assert_eq!(resample.process(vec![4, -4, 5, -5]), [5, -5]);
+    }
+
+    #[test]
+    fn downmix_averages_channels() {
+        let mut stereo = Downmix { channels: 2 };
+        assert_eq!(
+            stereo.process(vec![100, 200, -100, -300, i16::MAX, i16::MAX]),
+            [150, -200, i16::MAX]
+        );
+
+        let mut three = Downmix { channels: 3 };
+        assert_eq!(
+            three.process(vec![3, 6, 9, i16::MIN, i16::MIN, i16::MIN]),
+            [6, i16::MIN]
+        );
+    }
+
+    #[test]
+    fn vad_passes_quiet_audio_during_hangover() {
+        let mut vad = Vad {
+            hangover: 4,
+            remaining: 0,
+        };
+        assert_eq!(vad.process(vec![10; 2]), [0; 2]);
+        assert_eq!(vad.process(vec![1000; 2]), [1000; 2]);
+        assert_eq!(vad.process(vec![10; 2]), [10; 2]);
+        assert_eq!(vad.process(vec![10; 2]), [10; 2]);
+        assert_eq!(vad.process(vec![10; 2]), [0; 2]);
+    }
+
+    #[test]
+    fn buffer_collects_until_full() {
+        let mut buffer = buffer(4);
+        assert!(buffer.process(vec![1, 2]).is_empty());
+        assert_eq!(buffer.process(vec![3, 4, 5]), [1, 2, 3, 4, 5]);
+        assert!(buffer.process(vec![6]).is_empty());
+        assert_eq!(buffer.flush(), [6]);
+        assert!(buffer.flush().is_empty());
+    }
+
+    #[test]
+    fn flush_drains_stages_in_order() {
+        let mut pipeline = Pipeline {
+            stages: vec![
+                (StageKind::Buffer, Box::new(buffer(4))),
+                (StageKind::Buffer, Box::new(buffer(10))),
+            ],
+        };
+        assert!(pipeline.process(vec![1, 2, 3, 4, 5]).is_empty());
+        assert!(pipeline.process(vec![6, 7]).is_empty());
+        assert_eq!(pipeline.flush(), [1, 2, 3, 4, 5, 6, 7]);
+        assert!(pipeline.flush().is_empty());
+    }
+
+    #[test]
+    fn parses_stage_lists() {
+        assert_eq!(
+            parse_stages(" downmix, resample ,buffer,").unwrap(),
+            [StageKind::Downmix, StageKind::Resample, StageKind::Buffer]
+        );
+        assert_eq!(
+            parse_stages(DEFAULT_STAGES).unwrap(),
+            [
+                StageKind::Downmix,
+                StageKind::Denoise,
+                StageKind::Resample,
+                StageKind::Agc,
+                StageKind::Buffer
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_stages_that_dont_fit_together() {
+        let error = parse_stages("resample,denoise").unwrap_err();
+        assert!(error.starts_with("denoise stage"), "{}", error);
+        let error = parse_stages("downmix,downmix,resample").unwrap_err();
+        assert!(error.contains("already mono"), "{}", error);
+        let error = parse_stages("downmix").unwrap_err();
+        assert!(error.contains("recognizer needs"), "{}", error);
+        let error = parse_stages("downmix,echo,resample").unwrap_err();
+        assert!(error.contains("unknown audio stage `echo`"), "{}", error);
+    }
+}
@@ -0,0 +1,586 @@
+//! English inverse text normalization.
+//!
+//! Works on Vosk's lowercase, unpunctuated output. Standalone numbers below ten are left
+//! spelled out ("one of them"), as are ordinals below tenth, unless they are part of a time,
+//! date, amount or percentage.
+
+const UNITS: &[&str] = &[
+    "zero",
+    "one",
+    "two",
+    "three",
+    "four",
+    "five",
+    "six",
+    "seven",
+    "eight",
+    "nine",
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+
+const ORDINAL_UNITS: &[&str] = &[
+    "zeroth",
+    "first",
+    "second",
+    "third",
+    "fourth",
+    "fifth",
+    "sixth",
+    "seventh",
+    "eighth",
+    "ninth",
+    "tenth",
+    "eleventh",
+    "twelfth",
+    "thirteenth",
+    "fourteenth",
+    "fifteenth",
+    "sixteenth",
+    "seventeenth",
+    "eighteenth",
+    "nineteenth",
+];
+
+const TENS: &[&str] = &[
+    "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+
+const ORDINAL_TENS: &[&str] = &[
+    "twentieth",
+    "thirtieth",
+    "fortieth",
+    "fiftieth",
+    "sixtieth",
+    "seventieth",
+    "eightieth",
+    "ninetieth",
+];
+
+const SCALES: &[(&str, &str, u64)] = &[
+    ("thousand", "thousandth", 1_000),
+    ("million", "millionth", 1_000_000),
+    ("billion", "billionth", 1_000_000_000),
+];
+
+const MONTHS: &[&str] = &[
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Centuries of years read in pairs ("fourteen ninety two", "twenty twenty six"). They start
+/// above the clock hours, so "ten twenty" stays two numbers.
+const YEAR_CENTURIES: std::ops::RangeInclusive<u64> = 14..=20;
+
+const CURRENCIES: &[(&str, &str)] = &[
+    ("dollar", "$"),
+    ("dollars", "$"),
+    ("bucks", "$"),
+    ("euro", "€"),
+    ("euros", "€"),
+    ("pound", "£"),
+    ("pounds", "£"),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Word {
+    Unit(u64),
+    Teen(u64),
+    Tens(u64),
+    Hundred,
+    Scale(u64),
+}
+
+/// Classifies a number word, returning whether it is in ordinal form.
+fn classify(word: &str) -> Option<(Word, bool)> {
+    let small = |value: usize| {
+        if value < 10 {
+            Word::Unit(value as u64)
+        } else {
+            Word::Teen(value as u64)
+        }
+    };
+
+    if let Some(value) = UNITS.iter().position(|w| *w == word) {
+        return Some((small(value), false));
+    }
+    if let Some(value) = ORDINAL_UNITS.iter().position(|w| *w == word) {
+        return Some((small(value), true));
+    }
+    if let Some(index) = TENS.iter().position(|w| *w == word) {
+        return Some((Word::Tens(20 + 10 * index as u64), false));
+    }
+    if let Some(index) = ORDINAL_TENS.iter().position(|w| *w == word) {
+        return Some((Word::Tens(20 + 10 * index as u64), true));
+    }
+    match word {
+        "hundred" => return Some((Word::Hundred, false)),
+        "hundredth" => return Some((Word::Hundred, true)),
+        _ => {}
+    }
+    SCALES.iter().find_map(|(cardinal, ordinal, scale)| {
+        if word == *cardinal {
+            Some((Word::Scale(*scale), false))
+        } else if word == *ordinal {
+            Some((Word::Scale(*scale), true))
+        } else {
+            None
+        }
+    })
+}
+
+struct Number {
+    value: u64,
+    /// Words consumed.
+    len: usize,
+    ordinal: bool,
+}
+
+impl Number {
+    /// A lone word below ten, which reads better spelled out.
+    fn is_small(&self) -> bool {
+        self.len == 1 && self.value < 10
+    }
+}
+
+/// Parses the longest number at the start of `words`.
+fn parse_number(words: &[&str]) -> Option<Number> {
+    let mut total = 0;
+    let mut current = 0;
+    let mut last: Option<Word> = None;
+    let mut last_scale = u64::MAX;
+    let mut len = 0;
+    let mut i = 0;
+
+    while i < words.len() {
+        let Some((word, ordinal)) = classify(words[i]) else {
+            // "one hundred and five"
+            let continues = words[i] == "and"
+                && matches!(last, Some(Word::Hundred | Word::Scale(_)))
+                && words
+                    .get(i + 1)
+                    .and_then(|w| classify(w))
+                    .is_some_and(|(w, _)| {
+                        matches!(w, Word::Unit(1..) | Word::Teen(_) | Word::Tens(_))
+                    });
+            if continues {
+                i += 1;
+                continue;
+            }
+            break;
+        };
+
+        let allowed = match (last, word) {
+            (Some(Word::Unit(0)), _) => false,
+            (_, Word::Unit(0)) => last.is_none(),
+            (None, Word::Hundred | Word::Scale(_)) => false,
+            (None, _) => true,
+            (Some(Word::Tens(_)), Word::Unit(_)) => current % 10 == 0,
+            (
+                Some(Word::Hundred | Word::Scale(_)),
+                Word::Unit(_) | Word::Teen(_) | Word::Tens(_),
+            ) => true,
+            (Some(Word::Unit(_) | Word::Teen(_) | Word::Tens(_)), Word::Hundred) => current < 100,
+            (
+                Some(Word::Unit(_) | Word::Teen(_) | Word::Tens(_) | Word::Hundred),
+                Word::Scale(scale),
+            ) => scale < last_scale && current > 0,
+            _ => false,
+        };
+        if !allowed {
+            break;
+        }
+
+        match word {
+            Word::Unit(value) | Word::Teen(value) | Word::Tens(value) => current += value,
+            Word::Hundred => current *= 100,
+            Word::Scale(scale) => {
+                total += current * scale;
+                current = 0;
+                last_scale = scale;
+            }
+        }
+        last = Some(word);
+        i += 1;
+        len = i;
+
+        if ordinal {
+            return Some(Number {
+                value: total + current,
+                len,
+                ordinal: true,
+            });
+        }
+    }
+
+    (len > 0).then_some(Number {
+        value: total + current,
+        len,
+        ordinal: false,
+    })
+}
+
+/// Parses a two-digit group such as "twenty six" or "fifteen".
+fn parse_two_digits(words: &[&str]) -> Option<(u64, usize)> {
+    match words.first().and_then(|w| classify(w))? {
+        (Word::Teen(value), false) => Some((value, 1)),
+        (Word::Tens(tens), false) => match words.get(1).and_then(|w| classify(w)) {
+            Some((Word::Unit(unit @ 1..), false)) => Some((tens + unit, 2)),
+            _ => Some((tens, 1)),
+        },
+        _ => None,
+    }
+}
+
+/// Parses a year read in pairs ("twenty twenty six", "nineteen oh five") or in full
+/// ("two thousand twenty six").
+fn parse_year(words: &[&str]) -> Option<(u64, usize)> {
+    if let Some((century, used)) = parse_two_digits(words) {
+        let rest = &words[used..];
+        if rest.first() == Some(&"hundred") {
+            return Some((century * 100, used + 1));
+        }
+        if rest.first() == Some(&"oh") {
+            if let Some((Word::Unit(unit @ 1..), false)) = rest.get(1).and_then(|w| classify(w)) {
+                return Some((century * 100 + unit, used + 2));
+            }
+        }
+        if let Some((year, year_used)) = parse_two_digits(rest) {
+            return Some((century * 100 + year, used + year_used));
+        }
+    }
+
+    parse_number(words)
+        .filter(|n| !n.ordinal && (1000..3000).contains(&n.value))
+        .map(|n| (n.value, n.len))
+}
+
+fn ordinal_suffix(value: u64) -> &'static str {
+    match (value % 10, value % 100) {
+        (_, 11..=13) => "th",
+        (1, _) => "st",
+        (2, _) => "nd",
+        (3, _) => "rd",
+        _ => "th",
+    }
+}
+
+fn month_name(word: &str) -> Option<String> {
+    MONTHS.contains(&word).then(|| {
+        let mut chars = word.chars();
+        chars
+            .next()
+            .map(|first| first.to_uppercase().chain(chars).collect())
+            .unwrap_or_default()
+    })
+}
+
+/// "march twenty second [twenty twenty six]" or "the twenty second of march".
+fn date(words: &[&str]) -> Option<(String, usize)> {
+    if let Some(month) = words.first().and_then(|w| month_name(w)) {
+        // "may" is usually the verb unless an ordinal follows
+        let day = parse_number(&words[1..])
+            .filter(|n| (1..=31).contains(&n.value) && (n.ordinal || words[0] != "may"))?;
+        return Some(with_year(
+            format!("{} {}", month, day.value),
+            words,
+            1 + day.len,
+        ));
+    }
+
+    let day = parse_number(words).filter(|n| n.ordinal && (1..=31).contains(&n.value))?;
+    if words.get(day.len) != Some(&"of") {
+        return None;
+    }
+    let month = words.get(day.len + 1).and_then(|w| month_name(w))?;
+    Some(with_year(
+        format!("{} {}", month, day.value),
+        words,
+        day.len + 2,
+    ))
+}
+
+fn with_year(mut written: String, words: &[&str], used: usize) -> (String, usize) {
+    match parse_year(&words[used..]) {
+        Some((year, year_used)) => {
+            written.push_str(&format!(", {}", year));
+            (written, used + year_used)
+        }
+        None => (written, used),
+    }
+}
+
+fn meridiem(words: &[&str]) -> Option<(&'static str, usize)> {
+    match words {
+        ["am", ..] => Some(("AM", 1)),
+        ["pm", ..] => Some(("PM", 1)),
+        ["a", "m", ..] => Some(("AM", 2)),
+        ["p", "m", ..] => Some(("PM", 2)),
+        _ => None,
+    }
+}
+
+/// "at three thirty", "nine oh five pm", "seven o'clock", "eleven am". Without "at" before it,
+/// "o'clock" or AM/PM, a pair like "five fifty" is more likely two numbers than a time.
+fn time(words: &[&str], after_at: bool) -> Option<(String, usize)> {
+    let hour = match words.first().and_then(|w| classify(w))? {
+        (Word::Unit(hour @ 1..) | Word::Teen(hour @ 10..=12), false) => hour,
+        _ => return None,
+    };
+    let rest = &words[1..];
+    let on_the_hour = matches!(rest, ["o'clock" | "oclock", ..]);
+
+    let (minutes, used) = match rest {
+        ["o'clock" | "oclock", ..] => (0, 1),
+        ["oh", minute, ..] => match classify(minute) {
+            Some((Word::Unit(minute @ 1..), false)) => (minute, 2),
+            _ => return None,
+        },
+        _ => match parse_two_digits(rest) {
+            Some((minutes, used)) if minutes < 60 => (minutes, used),
+            _ => {
+                let (meridiem, used) = meridiem(rest)?;
+                return Some((format!("{} {}", hour, meridiem), 1 + used));
+            }
+        },
+    };
+
+    let mut written = format!("{}:{:02}", hour, minutes);
+    let mut used = 1 + used;
+    if let Some((meridiem, meridiem_used)) = meridiem(&words[used..]) {
+        written.push_str(&format!(" {}", meridiem));
+        used += meridiem_used;
+    } else if !after_at && !on_the_hour {
+        return None;
+    }
+    Some((written, used))
+}
+
+/// A year read in pairs outside a date: "nineteen ninety nine", "nineteen oh five".
+fn year(words: &[&str]) -> Option<(String, usize)> {
+    let century = match words.first().and_then(|w| classify(w))? {
+        (Word::Teen(value) | Word::Tens(value), false) if YEAR_CENTURIES.contains(&value) => value,
+        _ => return None,
+    };
+    let (year, used) = match &words[1..] {
+        ["oh", unit, ..] => match classify(unit) {
+            Some((Word::Unit(unit @ 1..), false)) => (unit, 2),
+            _ => return None,
+        },
+        rest => parse_two_digits(rest)?,
+    };
+    Some(((century * 100 + year).to_string(), 1 + used))
+}
+
+/// Cardinals, ordinals and decimals, with an optional currency or percent.
+fn quantity(words: &[&str]) -> Option<(String, usize)> {
+    let number = parse_number(words)?;
+    let mut used = number.len;
+
+    if number.ordinal {
+        return (number.value >= 10).then(|| {
+            (
+                format!("{}{}", number.value, ordinal_suffix(number.value)),
+                used,
+            )
+        });
+    }
+
+    let mut written = number.value.to_string();
+    let mut decimal = false;
+    if words.get(used) == Some(&"point") {
+        let digits: String = words[used + 1..]
+            .iter()
+            .map_while(|w| match classify(w) {
+                Some((Word::Unit(digit), false)) => Some(digit.to_string()),
+                _ if *w == "oh" => Some(String::from("0")),
+                _ => None,
+            })
+            .collect();
+        if !digits.is_empty() {
+            used += 1 + digits.len();
+            written = format!("{}.{}", written, digits);
+            decimal = true;
+        }
+    }
+
+    let rest = &words[used..];
+    if let Some((_, symbol)) = CURRENCIES
+        .iter()
+        .find(|(name, _)| rest.first() == Some(name))
+    {
+        used += 1;
+        let mut cents = String::new();
+        if !decimal && words.get(used) == Some(&"and") {
+            if let Some(amount) = parse_number(&words[used + 1..]) {
+                let unit = words.get(used + 1 + amount.len);
+                if !amount.ordinal && amount.value < 100 && matches!(unit, Some(&"cent" | &"cents"))
+                {
+                    cents = format!(".{:02}", amount.value);
+                    used += 2 + amount.len;
+                }
+            }
+        }
+        return Some((format!("{}{}{}", symbol, written, cents), used));
+    }
+
+    match rest {
+        ["percent", ..] => return Some((format!("{}%", written), used + 1)),
+        ["per", "cent", ..] => return Some((format!("{}%", written), used + 2)),
+        _ => {}
+    }
+
+    (decimal || !number.is_small()).then_some((written, used))
+}
+
+pub fn normalize(text: &str) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut output: Vec<String> = Vec::with_capacity(words.len());
+    let mut i = 0;
+
+    while i < words.len() {
+        let rest = &words[i..];
+        let after_at = i > 0 && words[i - 1] == "at";
+        match date(rest)
+            .or_else(|| time(rest, after_at))
+            .or_else(|| year(rest))
+            .or_else(|| quantity(rest))
+        {
+            Some((written, used)) => {
+                output.push(written);
+                i += used;
+            }
+            None => {
+                output.push(words[i].to_string());
+                i += 1;
+            }
+        }
+    }
+
+    output.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+
+    #[test]
+    fn times_need_context() {
+        assert_eq!(normalize("see you at three thirty"), "see you at 3:30");
+        assert_eq!(normalize("at five fifty"), "at 5:50");
+        assert_eq!(normalize("nine oh five pm"), "9:05 PM");
+        assert_eq!(normalize("seven o'clock"), "7:00");
+        assert_eq!(normalize("eleven am"), "11 AM");
+        assert_eq!(normalize("five fifty"), "five 50");
+        assert_eq!(normalize("ten twenty year olds"), "10 20 year olds");
+    }
+
+    #[test]
+    fn years() {
+        assert_eq!(normalize("nineteen ninety nine"), "1999");
+        assert_eq!(normalize("fourteen ninety two"), "1492");
+        assert_eq!(normalize("nineteen oh five"), "1905");
+        assert_eq!(normalize("in twenty twenty six"), "in 2026");
+        assert_eq!(normalize("two thousand twenty six"), "2026");
+        assert_eq!(normalize("twenty five"), "25");
+        assert_eq!(
+            normalize("march twenty second twenty twenty six"),
+            "March 22, 2026"
+        );
+    }
+
+    fn check(cases: &[(&str, &str)]) {
+        for (input, expected) in cases {
+            assert_eq!(normalize(input), *expected, "normalizing {:?}", input);
+        }
+    }
+
+    #[test]
+    fn cardinals() {
+        check(&[
+            ("twelve", "12"),
+            ("forty two", "42"),
+            ("one hundred and five", "105"),
+            ("three thousand four hundred", "3400"),
+            ("two million three thousand", "2003000"),
+            ("zero", "zero"),
+            ("nine", "nine"),
+            ("three point one four", "3.14"),
+        ]);
+    }
+
+    #[test]
+    fn ordinals() {
+        check(&[
+            ("the twenty first time", "the 21st time"),
+            ("eleventh hour", "11th hour"),
+            ("one hundred and third", "103rd"),
+            ("the first one", "the first one"),
+            ("ninth", "ninth"),
+        ]);
+    }
+
+    #[test]
+    fn dates() {
+        check(&[
+            ("june first", "June 1"),
+            ("the third of july", "the July 3"),
+            (
+                "december twenty fifth nineteen ninety nine",
+                "December 25, 1999",
+            ),
+            ("may fourth", "May 4"),
+            ("may two", "may two"),
+        ]);
+    }
+
+    #[test]
+    fn currency() {
+        check(&[
+            ("five dollars", "$5"),
+            ("twenty euros", "€20"),
+            ("one pound", "£1"),
+            ("three dollars and fifty cents", "$3.50"),
+            ("two point five dollars", "$2.5"),
+        ]);
+    }
+
+    #[test]
+    fn percentages() {
+        check(&[
+            ("fifty percent", "50%"),
+            ("five per cent", "5%"),
+            ("zero point five percent", "0.5%"),
+        ]);
+    }
+
+    #[test]
+    fn leaves_small_numbers_and_words_alone() {
+        check(&[
+            ("one of them", "one of them"),
+            ("i have two cats", "i have two cats"),
+            ("the second time", "the second time"),
+            ("point taken", "point taken"),
+            ("and then", "and then"),
+            ("", ""),
+        ]);
+    }
+}
@@ -0,0 +1,33 @@
+mod en;
+
+use super::TextPostProcessor;
+use serenity::async_trait;
+
+/// Rewrites spelled-out cardinals, ordinals, times, dates, currency and percentages in
+/// written form, e.g. "twenty five dollars" → "$25".
+pub struct InverseTextNormalizer {
+    normalize: fn(&str) -> String,
+}
+
+impl InverseTextNormalizer {
+    /// Returns `None` for languages without normalization rules.
+    pub fn for_language(language: &str) -> Option<Self> {
+        match language {
+            "en" => Some(Self {
+                normalize: en::normalize,
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[async_trait]
+impl TextPostProcessor for InverseTextNormalizer {
+    fn name(&self) -> &'static str {
+        "normalize"
+    }
+
+    async fn process(&self, text: String) -> String {
+        (self.normalize)(&text)
+    }
+}
@@ -0,0 +1,71 @@
+mod itn;
+mod profanity;
+mod punctuation;
+mod punctuation_model;
+
+use serenity::async_trait;
+use std::sync::Arc;
+use tracing::{trace, warn};
+
+pub use itn::InverseTextNormalizer;
+pub use profanity::ProfanityFilter;
+pub use punctuation::{Capitalizer, RulePunctuator};
+pub use punctuation_model::PunctuationModel;
+
+/// A text transformation applied to each finalized utterance before delivery.
+#[async_trait]
+pub trait TextPostProcessor: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn process(&self, text: String) -> String;
+}
+
+pub type Chain = Vec<Arc<dyn TextPostProcessor>>;
+
+pub const STAGE_NAMES: &[&str] = &["normalize", "punctuate", "capitalize", "punctuation-model"];
+
+pub fn default_stages() -> Vec<String> {
+    ["normalize", "punctuate", "capitalize"]
+        .into_iter()
+        .map(String::from)
+        .collect()
+}
+
+/// Builds a chain from stage names, skipping stages that can't be used here.
+///
+/// `language` is the transcript language, used by language-specific stages.
+pub fn build_chain(
+    stages: &[String],
+    language: &str,
+    punctuation_model_cmd: Option<&[String]>,
+) -> Chain {
+    let mut chain: Chain = Vec::new();
+
+    for stage in stages {
+        match stage.as_str() {
+            "normalize" => match InverseTextNormalizer::for_language(language) {
+                Some(normalizer) => chain.push(Arc::new(normalizer)),
+                None => warn!(language, "No text normalization rules for language"),
+            },
+            "punctuate" => chain.push(Arc::new(RulePunctuator)),
+            "capitalize" => chain.push(Arc::new(Capitalizer)),
+            "punctuation-model" => match punctuation_model_cmd {
+                Some(command) => chain.push(Arc::new(PunctuationModel::new(command.to_vec()))),
+                None => {
+                    warn!("punctuation-model stage requested but PUNCTUATION_MODEL_CMD is not set")
+                }
+            },
+            other => warn!(stage = other, "Unknown post-processing stage"),
+        }
+    }
+
+    chain
+}
+
+pub async fn apply(chain: &Chain, mut text: String) -> String {
+    for stage in chain {
+        text = stage.process(text).await;
+        trace!(stage = stage.name(), text = %text, "Post-processed transcript");
+    }
+    text
+}
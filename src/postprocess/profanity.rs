@@ -0,0 +1,220 @@
+use super::TextPostProcessor;
+use serenity::async_trait;
+use std::collections::HashSet;
+
+// Exact words with their inflections, so innocent words like "arsenal" or "prickly"
+// aren't caught by a shared prefix
+const BUILT_IN_WORDS: &[&str] = &[
+    "arse",
+    "arsehole",
+    "arseholes",
+    "arses",
+    "asshole",
+    "assholes",
+    "bastard",
+    "bastards",
+    "bitch",
+    "bitched",
+    "bitches",
+    "bitching",
+    "bitchy",
+    "bollock",
+    "bollocks",
+    "bullshit",
+    "bullshits",
+    "bullshitted",
+    "bullshitting",
+    "cock",
+    "cocks",
+    "cocksucker",
+    "cocksuckers",
+    "cunt",
+    "cunts",
+    "dick",
+    "dickhead",
+    "dickheads",
+    "dicks",
+    "fag",
+    "faggot",
+    "faggots",
+    "fags",
+    "fuck",
+    "fucked",
+    "fucker",
+    "fuckers",
+    "fuckin",
+    "fucking",
+    "fucks",
+    "motherfucker",
+    "motherfuckers",
+    "motherfucking",
+    "nigga",
+    "niggas",
+    "nigger",
+    "niggers",
+    "piss",
+    "pissed",
+    "prick",
+    "pricks",
+    "retard",
+    "retarded",
+    "retards",
+    "shit",
+    "shite",
+    "shits",
+    "shitted",
+    "shitting",
+    "shitty",
+    "slut",
+    "sluts",
+    "slutty",
+    "twat",
+    "twats",
+    "wank",
+    "wanked",
+    "wanker",
+    "wankers",
+    "wanking",
+    "wanks",
+    "whore",
+    "whores",
+];
+
+/// Masks or removes profane words.
+///
+/// Matching is per word and case-insensitive, ignoring surrounding punctuation.
+#[derive(Clone)]
+pub struct ProfanityFilter {
+    remove: bool,
+    words: HashSet<String>,
+    prefixes: Vec<String>,
+    allowed: HashSet<String>,
+}
+
+impl ProfanityFilter {
+    /// `blocked` extends the built-in list and `allowed` exempts words from it.
+    pub fn new(remove: bool, blocked: &[String], allowed: &[String]) -> Self {
+        let mut words = HashSet::new();
+        let mut prefixes = Vec::new();
+
+        let entries = BUILT_IN_WORDS
+            .iter()
+            .copied()
+            .chain(blocked.iter().map(String::as_str));
+        for entry in entries {
+            let entry = entry.to_lowercase();
+            match entry.strip_suffix('*') {
+                Some(prefix) if !prefix.is_empty() => prefixes.push(prefix.to_string()),
+                Some(_) => {}
+                None => {
+                    words.insert(entry);
+                }
+            }
+        }
+
+        Self {
+            remove,
+            words,
+            prefixes,
+            allowed: allowed.iter().map(|word| word.to_lowercase()).collect(),
+        }
+    }
+
+    fn is_profane(&self, word: &str) -> bool {
+        let word = word.to_lowercase();
+        !self.allowed.contains(&word)
+            && (self.words.contains(&word)
+                || self.prefixes.iter().any(|prefix| word.starts_with(prefix)))
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut output: Vec<String> = Vec::new();
+
+        for token in text.split_whitespace() {
+            let start = token.find(char::is_alphanumeric).unwrap_or(token.len());
+            let end = token
+                .rfind(char::is_alphanumeric)
+                .map(|i| i + token[i..].chars().next().map_or(1, char::len_utf8))
+                .unwrap_or(start);
+            let (leading, word, trailing) = (&token[..start], &token[start..end], &token[end..]);
+
+            if word.is_empty() || !self.is_profane(word) {
+                output.push(token.to_string());
+            } else if self.remove {
+                // Keep sentence punctuation so the caption still reads as a sentence
+                if let Some(previous) = output.last_mut() {
+                    previous.push_str(trailing);
+                }
+            } else {
+                let mut chars = word.chars();
+                let masked: String = chars
+                    .next()
+                    .into_iter()
+                    .chain(chars.map(|c| if c.is_alphanumeric() { '*' } else { c }))
+                    .collect();
+                output.push(format!("{}{}{}", leading, masked, trailing));
+            }
+        }
+
+        output.join(" ")
+    }
+}
+
+#[async_trait]
+impl TextPostProcessor for ProfanityFilter {
+    fn name(&self) -> &'static str {
+        "profanity"
+    }
+
+    async fn process(&self, text: String) -> String {
+        self.apply(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(remove: bool) -> ProfanityFilter {
+        ProfanityFilter::new(remove, &[], &[])
+    }
+
+    #[test]
+    fn masks_all_but_the_first_letter() {
+        assert_eq!(
+            filter(false).apply("oh shit, not again"),
+            "oh s***, not again"
+        );
+    }
+
+    #[test]
+    fn removes_words_but_keeps_punctuation() {
+        assert_eq!(filter(true).apply("what the fuck."), "what the.");
+        assert_eq!(filter(true).apply("shit"), "");
+    }
+
+    #[test]
+    fn matches_case_insensitively() {
+        assert_eq!(filter(false).apply("FUCK Bastards"), "F*** B*******");
+    }
+
+    #[test]
+    fn matches_whole_words_only() {
+        let text = "the class visited arsenal and scunthorpe";
+        assert_eq!(filter(false).apply(text), text);
+    }
+
+    #[test]
+    fn applies_the_custom_word_lists() {
+        let filter = ProfanityFilter::new(
+            false,
+            &[String::from("Heck*"), String::from("darn")],
+            &[String::from("Bastard")],
+        );
+        assert_eq!(
+            filter.apply("darn it, heckin bastard"),
+            "d*** it, h***** bastard"
+        );
+        assert_eq!(filter.apply("heck"), "h***");
+    }
+}
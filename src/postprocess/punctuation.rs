@@ -0,0 +1,162 @@
+use super::TextPostProcessor;
+use serenity::async_trait;
+
+const QUESTION_WORDS: &[&str] = &[
+    "who", "whom", "whose", "what", "when", "where", "why", "how", "which",
+];
+
+// Auxiliaries that start a yes/no question when they open an utterance
+const QUESTION_AUXILIARIES: &[&str] = &[
+    "am",
+    "is",
+    "are",
+    "was",
+    "were",
+    "do",
+    "does",
+    "did",
+    "can",
+    "could",
+    "will",
+    "would",
+    "shall",
+    "should",
+    "may",
+    "might",
+    "must",
+    "have",
+    "has",
+    "had",
+    "isn't",
+    "aren't",
+    "wasn't",
+    "weren't",
+    "don't",
+    "doesn't",
+    "didn't",
+    "can't",
+    "couldn't",
+    "won't",
+    "wouldn't",
+    "shouldn't",
+    "haven't",
+    "hasn't",
+    "hadn't",
+];
+
+const QUESTION_TAGS: &[&str] = &["right", "or not", "isn't it", "don't you", "aren't you"];
+
+/// Adds terminal punctuation, using a question mark for utterances that read as questions.
+pub struct RulePunctuator;
+
+impl RulePunctuator {
+    fn is_question(text: &str) -> bool {
+        let lower = text.to_lowercase();
+        let first = lower.split_whitespace().next().unwrap_or("");
+
+        QUESTION_WORDS.contains(&first)
+            || QUESTION_AUXILIARIES.contains(&first)
+            || QUESTION_TAGS
+                .iter()
+                .any(|tag| lower.ends_with(&format!(" {}", tag)))
+    }
+}
+
+#[async_trait]
+impl TextPostProcessor for RulePunctuator {
+    fn name(&self) -> &'static str {
+        "punctuate"
+    }
+
+    async fn process(&self, text: String) -> String {
+        let trimmed = text.trim_end();
+        if trimmed.is_empty() || trimmed.ends_with(['.', '?', '!']) {
+            return text;
+        }
+
+        let mark = if Self::is_question(trimmed) { '?' } else { '.' };
+        format!("{}{}", trimmed, mark)
+    }
+}
+
+/// Capitalizes sentence starts and the pronoun "I".
+pub struct Capitalizer;
+
+#[async_trait]
+impl TextPostProcessor for Capitalizer {
+    fn name(&self) -> &'static str {
+        "capitalize"
+    }
+
+    async fn process(&self, text: String) -> String {
+        let mut output = String::with_capacity(text.len());
+        let mut sentence_start = true;
+
+        for (i, word) in text.split(' ').enumerate() {
+            if i > 0 {
+                output.push(' ');
+            }
+
+            let is_pronoun = word == "i"
+                || word.starts_with("i'")
+                || (word.len() == 2
+                    && word.starts_with('i')
+                    && word.ends_with(['.', '?', '!', ',']));
+            if sentence_start || is_pronoun {
+                let mut chars = word.chars();
+                if let Some(first) = chars.next() {
+                    output.extend(first.to_uppercase());
+                    output.push_str(chars.as_str());
+                }
+            } else {
+                output.push_str(word);
+            }
+
+            if !word.is_empty() {
+                sentence_start = word.ends_with(['.', '?', '!']);
+            }
+        }
+
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn run(processor: &dyn TextPostProcessor, text: &str) -> String {
+        processor.process(text.to_string()).await
+    }
+
+    #[tokio::test]
+    async fn capitalizes_sentences_and_i() {
+        assert_eq!(
+            run(&Capitalizer, "hello there. i think i'm fine? yes i.").await,
+            "Hello there. I think I'm fine? Yes I."
+        );
+        assert_eq!(run(&Capitalizer, "inside it").await, "Inside it");
+        assert_eq!(run(&Capitalizer, "").await, "");
+    }
+
+    #[tokio::test]
+    async fn adds_terminal_punctuation_once() {
+        assert_eq!(run(&RulePunctuator, "it works").await, "it works.");
+        assert_eq!(run(&RulePunctuator, "it works  ").await, "it works.");
+        assert_eq!(run(&RulePunctuator, "it works!").await, "it works!");
+        assert_eq!(run(&RulePunctuator, "done.").await, "done.");
+        assert_eq!(run(&RulePunctuator, "   ").await, "   ");
+    }
+
+    #[tokio::test]
+    async fn detects_questions() {
+        assert_eq!(run(&RulePunctuator, "where is it").await, "where is it?");
+        assert_eq!(run(&RulePunctuator, "Did you eat").await, "Did you eat?");
+        assert_eq!(
+            run(&RulePunctuator, "that's the one right").await,
+            "that's the one right?"
+        );
+        assert_eq!(run(&RulePunctuator, "I know why").await, "I know why.");
+        assert_eq!(run(&RulePunctuator, "bright light").await, "bright light.");
+    }
+}
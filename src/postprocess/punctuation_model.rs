@@ -0,0 +1,76 @@
+use super::TextPostProcessor;
+use serenity::async_trait;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tracing::warn;
+
+const MODEL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Restores punctuation and casing with a local model run as an external command.
+///
+/// The command gets the utterance on stdin and must print the punctuated text on stdout,
+/// e.g. a wrapper around Vosk's `recasepunc` model. On any failure the text passes through
+/// unchanged so captions keep flowing.
+pub struct PunctuationModel {
+    command: Vec<String>,
+}
+
+impl PunctuationModel {
+    pub fn new(command: Vec<String>) -> Self {
+        Self { command }
+    }
+
+    async fn run(&self, text: &str) -> Result<String, String> {
+        let (program, args) = self.command.split_first().ok_or("empty command")?;
+
+        let mut child = Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("failed to start {}: {}", program, e))?;
+
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        let output = tokio::time::timeout(MODEL_TIMEOUT, child.wait_with_output())
+            .await
+            .map_err(|_| String::from("timed out"))?
+            .map_err(|e| e.to_string())?;
+
+        if !output.status.success() {
+            return Err(format!("exited with {}", output.status));
+        }
+
+        let punctuated = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if punctuated.is_empty() {
+            return Err(String::from("no output"));
+        }
+        Ok(punctuated)
+    }
+}
+
+#[async_trait]
+impl TextPostProcessor for PunctuationModel {
+    fn name(&self) -> &'static str {
+        "punctuation-model"
+    }
+
+    async fn process(&self, text: String) -> String {
+        match self.run(&text).await {
+            Ok(punctuated) => punctuated,
+            Err(e) => {
+                warn!("Punctuation model failed, keeping original text: {}", e);
+                text
+            }
+        }
+    }
+}
@@ -0,0 +1,132 @@
+use crate::metrics::METRICS;
+use serenity::prelude::TypeMapKey;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+use vosk::{Model, Recognizer};
+
+pub const TARGET_SAMPLE_RATE: f32 = 16000.0;
+
+pub enum AcquireError {
+    /// `max_active` recognizers are already checked out.
+    Exhausted,
+    CreateFailed,
+}
+
+struct PoolState {
+    idle: Vec<Recognizer>,
+    active: usize,
+}
+
+/// Shares Vosk recognizers between speakers and sessions.
+///
+/// At most `max_active` recognizers exist at once; up to `max_idle` released ones are kept
+/// (after a reset) for reuse instead of being rebuilt from the model.
+pub struct RecognizerPool {
+    model: Arc<Model>,
+    model_name: String,
+    max_active: usize,
+    max_idle: usize,
+    state: Mutex<PoolState>,
+}
+
+impl RecognizerPool {
+    pub fn new(
+        model: Arc<Model>,
+        model_name: String,
+        max_active: usize,
+        max_idle: usize,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            model,
+            model_name,
+            max_active,
+            max_idle,
+            state: Mutex::new(PoolState {
+                idle: Vec::new(),
+                active: 0,
+            }),
+        })
+    }
+
+    pub fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    pub fn acquire(self: &Arc<Self>) -> Result<PooledRecognizer, AcquireError> {
+        let mut state = self.state.lock().unwrap();
+
+        if state.active >= self.max_active {
+            return Err(AcquireError::Exhausted);
+        }
+
+        let recognizer = match state.idle.pop() {
+            Some(recognizer) => recognizer,
+            None => Recognizer::new(&self.model, TARGET_SAMPLE_RATE).ok_or_else(|| {
+                METRICS.recognizer_failures.inc();
+                AcquireError::CreateFailed
+            })?,
+        };
+
+        state.active += 1;
+        self.update_gauges(&state);
+
+        Ok(PooledRecognizer {
+            pool: self.clone(),
+            recognizer: Some(recognizer),
+        })
+    }
+
+    fn release(&self, mut recognizer: Recognizer) {
+        let mut state = self.state.lock().unwrap();
+        state.active -= 1;
+
+        if state.idle.len() < self.max_idle {
+            recognizer.reset();
+            state.idle.push(recognizer);
+        }
+
+        self.update_gauges(&state);
+    }
+
+    fn update_gauges(&self, state: &PoolState) {
+        METRICS.active_recognizers.set(state.active as i64);
+        METRICS.idle_recognizers.set(state.idle.len() as i64);
+    }
+}
+
+/// A recognizer checked out of a `RecognizerPool`, returned to it on drop.
+pub struct PooledRecognizer {
+    pool: Arc<RecognizerPool>,
+    recognizer: Option<Recognizer>,
+}
+
+impl Deref for PooledRecognizer {
+    type Target = Recognizer;
+
+    fn deref(&self) -> &Recognizer {
+        self.recognizer
+            .as_ref()
+            .expect("recognizer present until drop")
+    }
+}
+
+impl DerefMut for PooledRecognizer {
+    fn deref_mut(&mut self) -> &mut Recognizer {
+        self.recognizer
+            .as_mut()
+            .expect("recognizer present until drop")
+    }
+}
+
+impl Drop for PooledRecognizer {
+    fn drop(&mut self) {
+        if let Some(recognizer) = self.recognizer.take() {
+            self.pool.release(recognizer);
+        }
+    }
+}
+
+pub struct RecognizerPoolKey;
+impl TypeMapKey for RecognizerPoolKey {
+    type Value = Arc<RecognizerPool>;
+}
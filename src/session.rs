@@ -0,0 +1,117 @@
+use crate::transcription::Receiver;
+use crate::tts::TtsReader;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::prelude::TypeMapKey;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub struct Session {
+    pub channel_id: ChannelId,
+    pub started_at: Instant,
+    pub receiver: Receiver,
+    /// Text channel read aloud into the voice channel, if linked with `!tts`.
+    pub tts: Option<TtsReader>,
+}
+
+pub struct SessionStatus {
+    pub channel_id: ChannelId,
+    pub tts_channel: Option<ChannelId>,
+    pub uptime: Duration,
+    pub driver_connected: bool,
+    pub speakers: usize,
+    pub recent_latency: Option<Duration>,
+}
+
+/// Active voice sessions, keyed by guild.
+#[derive(Clone, Default)]
+pub struct Sessions {
+    inner: Arc<Mutex<HashMap<GuildId, Session>>>,
+}
+
+impl Session {
+    /// Finalizes all pending speech and waits for it to reach every sink.
+    pub async fn close(self) {
+        self.receiver.finalize_all();
+        self.receiver.flush().await;
+    }
+}
+
+impl Sessions {
+    pub fn insert(&self, guild_id: GuildId, session: Session) {
+        self.inner.lock().unwrap().insert(guild_id, session);
+    }
+
+    pub fn remove(&self, guild_id: GuildId) -> Option<Session> {
+        self.inner.lock().unwrap().remove(&guild_id)
+    }
+
+    pub fn drain(&self) -> Vec<(GuildId, Session)> {
+        self.inner.lock().unwrap().drain().collect()
+    }
+
+    /// Links or unlinks the session's read-aloud channel; false when there is no session.
+    pub fn set_tts(&self, guild_id: GuildId, tts: Option<TtsReader>) -> bool {
+        match self.inner.lock().unwrap().get_mut(&guild_id) {
+            Some(session) => {
+                session.tts = tts;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn tts(&self, guild_id: GuildId) -> Option<TtsReader> {
+        self.inner
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .and_then(|session| session.tts.clone())
+    }
+
+    pub fn status(&self, guild_id: GuildId) -> Option<SessionStatus> {
+        self.inner
+            .lock()
+            .unwrap()
+            .get(&guild_id)
+            .map(|session| SessionStatus {
+                channel_id: session.channel_id,
+                tts_channel: session.tts.as_ref().map(TtsReader::channel_id),
+                uptime: session.started_at.elapsed(),
+                driver_connected: session.receiver.driver_connected(),
+                speakers: session.receiver.tracked_speakers(),
+                recent_latency: session.receiver.recent_latency(),
+            })
+    }
+
+    pub fn count(&self) -> usize {
+        self.inner.lock().unwrap().len()
+    }
+
+    pub fn disconnected_drivers(&self) -> usize {
+        self.inner
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|session| !session.receiver.driver_connected())
+            .count()
+    }
+}
+
+pub struct SessionsKey;
+impl TypeMapKey for SessionsKey {
+    type Value = Sessions;
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (hours, minutes, seconds) = (secs / 3600, (secs % 3600) / 60, secs % 60);
+
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
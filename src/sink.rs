@@ -0,0 +1,540 @@
+use crate::postprocess::{self, Chain, ProfanityFilter};
+use crate::translation::{SessionTranslator, Translation};
+use serenity::async_trait;
+use serenity::builder::{CreateAllowedMentions, CreateMessage, CreateWebhook, ExecuteWebhook};
+use serenity::http::Http;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::model::webhook::Webhook;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::fs::{self, File};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tracing::{debug, warn, Instrument, Span};
+
+const MAX_MESSAGE_LEN: usize = 1900;
+const MAX_WEBHOOK_USERNAME_LEN: usize = 80;
+const WEBHOOK_NAME: &str = "Voice Captions";
+
+/// Identifies the voice session a transcript belongs to.
+#[derive(Clone, Copy, Debug)]
+pub struct SessionContext {
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub session_id: u64,
+    pub started_at: SystemTime,
+}
+
+#[derive(Clone, Debug)]
+pub struct Transcript {
+    pub session: SessionContext,
+    pub ssrc: u32,
+    pub user_id: Option<UserId>,
+    /// Post-processed text, as posted and archived.
+    pub text: String,
+    /// Text exactly as the recognizer produced it.
+    pub raw_text: String,
+    /// `text` in the guild's target languages, filled in before delivery.
+    pub translations: Vec<Translation>,
+    pub started_at: SystemTime,
+}
+
+impl Transcript {
+    /// Offset of the utterance start from the beginning of the session.
+    pub fn offset(&self) -> Duration {
+        self.started_at
+            .duration_since(self.session.started_at)
+            .unwrap_or_default()
+    }
+
+    pub fn speaker_label(&self) -> String {
+        match self.user_id {
+            Some(user_id) => format!("<@{}>", user_id),
+            None => format!("Unknown speaker (SSRC {})", self.ssrc),
+        }
+    }
+}
+
+#[async_trait]
+pub trait TranscriptSink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn deliver(&self, transcript: &Transcript) -> Result<(), String>;
+
+    async fn flush(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+enum SinkMessage {
+    Transcript(Box<Transcript>),
+    Flush(oneshot::Sender<()>),
+}
+
+/// Hands finalized transcripts to the session's delivery task.
+#[derive(Clone)]
+pub struct TranscriptSender {
+    tx: mpsc::UnboundedSender<SinkMessage>,
+}
+
+impl TranscriptSender {
+    pub fn send(&self, transcript: Transcript) {
+        if self
+            .tx
+            .send(SinkMessage::Transcript(Box::new(transcript)))
+            .is_err()
+        {
+            warn!("Transcript delivery task has stopped, dropping transcript");
+        }
+    }
+
+    /// Waits until everything sent so far has been delivered and all sinks are flushed.
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.tx.send(SinkMessage::Flush(done_tx)).is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+}
+
+/// Spawns the task that post-processes and translates a session's transcripts and delivers
+/// them to its sinks in order.
+pub fn spawn_delivery(
+    post_processors: Chain,
+    translator: Option<SessionTranslator>,
+    sinks: Vec<Box<dyn TranscriptSink>>,
+    span: Span,
+) -> TranscriptSender {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+
+    tokio::spawn(
+        async move {
+            while let Some(message) = rx.recv().await {
+                match message {
+                    SinkMessage::Transcript(mut transcript) => {
+                        transcript.text =
+                            postprocess::apply(&post_processors, transcript.raw_text.clone()).await;
+                        if transcript.text != transcript.raw_text {
+                            debug!(
+                                raw = %transcript.raw_text,
+                                text = %transcript.text,
+                                "Post-processed transcript"
+                            );
+                        }
+                        // E.g. the profanity filter removed every word
+                        if transcript.text.trim().is_empty() {
+                            debug!(raw = %transcript.raw_text, "Dropped empty transcript");
+                        } else {
+                            if let Some(translator) = &translator {
+                                transcript.translations =
+                                    translator.translate(&transcript.text).await;
+                            }
+
+                            for sink in &sinks {
+                                if let Err(e) = sink.deliver(&transcript).await {
+                                    warn!(
+                                        sink = sink.name(),
+                                        "Failed to deliver transcript: {}", e
+                                    );
+                                }
+                            }
+                        }
+
+                        // Batch writes under load, but don't leave them buffered while idle
+                        if rx.is_empty() {
+                            flush_all(&sinks).await;
+                        }
+                    }
+                    SinkMessage::Flush(done) => {
+                        flush_all(&sinks).await;
+                        let _ = done.send(());
+                    }
+                }
+            }
+
+            flush_all(&sinks).await;
+        }
+        .instrument(span),
+    );
+
+    TranscriptSender { tx }
+}
+
+async fn flush_all(sinks: &[Box<dyn TranscriptSink>]) {
+    for sink in sinks {
+        if let Err(e) = sink.flush().await {
+            warn!(sink = sink.name(), "Failed to flush sink: {}", e);
+        }
+    }
+}
+
+/// Posts captions to the text channel the session was started from.
+pub struct ChannelSink {
+    http: Arc<Http>,
+    channel_id: ChannelId,
+    /// Applied to posted captions only, leaving other sinks untouched.
+    filter: Option<ProfanityFilter>,
+}
+
+impl ChannelSink {
+    pub fn new(http: Arc<Http>, channel_id: ChannelId, filter: Option<ProfanityFilter>) -> Self {
+        Self {
+            http,
+            channel_id,
+            filter,
+        }
+    }
+}
+
+#[async_trait]
+impl TranscriptSink for ChannelSink {
+    fn name(&self) -> &'static str {
+        "channel"
+    }
+
+    async fn deliver(&self, transcript: &Transcript) -> Result<(), String> {
+        let content = format!(
+            "**{}**: {}",
+            transcript.speaker_label(),
+            caption(transcript, self.filter.as_ref())
+        );
+
+        for chunk in split_message(&content, MAX_MESSAGE_LEN) {
+            let message = CreateMessage::new()
+                .content(chunk)
+                .allowed_mentions(CreateAllowedMentions::new());
+            self.channel_id
+                .send_message(&self.http, message)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct Speaker {
+    name: String,
+    avatar_url: Option<String>,
+}
+
+/// Posts captions through a channel webhook under each speaker's display name and avatar, so
+/// the channel reads as if the speakers had typed them.
+pub struct WebhookSink {
+    http: Arc<Http>,
+    guild_id: GuildId,
+    webhook: Webhook,
+    filter: Option<ProfanityFilter>,
+    speakers: Mutex<HashMap<UserId, Speaker>>,
+}
+
+impl WebhookSink {
+    /// Reuses the bot's caption webhook in `channel_id`, creating it if there is none yet.
+    pub async fn connect(
+        http: Arc<Http>,
+        guild_id: GuildId,
+        channel_id: ChannelId,
+        bot_user_id: UserId,
+        filter: Option<ProfanityFilter>,
+    ) -> Result<Self, String> {
+        let existing = channel_id
+            .webhooks(&http)
+            .await
+            .map_err(|e| format!("failed to list webhooks: {}", e))?
+            .into_iter()
+            .find(|webhook| {
+                webhook.token.is_some()
+                    && webhook.name.as_deref() == Some(WEBHOOK_NAME)
+                    && webhook.user.as_ref().map(|user| user.id) == Some(bot_user_id)
+            });
+
+        let webhook = match existing {
+            Some(webhook) => webhook,
+            None => channel_id
+                .create_webhook(&http, CreateWebhook::new(WEBHOOK_NAME))
+                .await
+                .map_err(|e| format!("failed to create webhook: {}", e))?,
+        };
+
+        Ok(Self {
+            http,
+            guild_id,
+            webhook,
+            filter,
+            speakers: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn speaker(&self, transcript: &Transcript) -> Speaker {
+        let Some(user_id) = transcript.user_id else {
+            return Speaker {
+                name: transcript.speaker_label(),
+                avatar_url: None,
+            };
+        };
+
+        if let Some(speaker) = self.speakers.lock().await.get(&user_id) {
+            return speaker.clone();
+        }
+
+        match self.guild_id.member(&self.http, user_id).await {
+            Ok(member) => {
+                let speaker = Speaker {
+                    name: webhook_username(member.display_name()),
+                    avatar_url: Some(member.face()),
+                };
+                self.speakers.lock().await.insert(user_id, speaker.clone());
+                speaker
+            }
+            // Not cached, so the lookup is retried on the next utterance
+            Err(e) => {
+                warn!(user_id = user_id.get(), "Failed to look up speaker: {}", e);
+                Speaker {
+                    name: format!("User {}", user_id),
+                    avatar_url: None,
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl TranscriptSink for WebhookSink {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn deliver(&self, transcript: &Transcript) -> Result<(), String> {
+        let speaker = self.speaker(transcript).await;
+        let content = caption(transcript, self.filter.as_ref());
+
+        for chunk in split_message(&content, MAX_MESSAGE_LEN) {
+            let mut message = ExecuteWebhook::new()
+                .content(chunk)
+                .username(speaker.name.clone())
+                .allowed_mentions(CreateAllowedMentions::new());
+            if let Some(avatar_url) = &speaker.avatar_url {
+                message = message.avatar_url(avatar_url.clone());
+            }
+            self.webhook
+                .execute(&self.http, false, message)
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Caption text with one line per translation, escaped for Discord.
+fn caption(transcript: &Transcript, filter: Option<&ProfanityFilter>) -> String {
+    let render = |text: &str| match filter {
+        Some(filter) => escape_markdown(&filter.apply(text)),
+        None => escape_markdown(text),
+    };
+
+    let mut content = render(&transcript.text);
+    for translation in &transcript.translations {
+        content.push_str(&format!(
+            "\n> `{}` {}",
+            translation.language,
+            render(&translation.text)
+        ));
+    }
+    content
+}
+
+/// Makes a display name acceptable as a webhook username.
+///
+/// Discord rejects usernames over 80 characters or containing "discord" or "clyde".
+fn webhook_username(name: &str) -> String {
+    let mut username = name.trim().to_string();
+    for reserved in ["discord", "clyde"] {
+        while let Some(start) = username.to_ascii_lowercase().find(reserved) {
+            // A zero-width space after the first letter breaks up the reserved word
+            username.insert(start + 1, '\u{200b}');
+        }
+    }
+
+    if username.is_empty() {
+        String::from("Unknown speaker")
+    } else {
+        username.chars().take(MAX_WEBHOOK_USERNAME_LEN).collect()
+    }
+}
+
+/// Appends transcripts to a per-session text file.
+pub struct ArchiveSink {
+    path: PathBuf,
+    file: Mutex<Option<BufWriter<File>>>,
+}
+
+impl ArchiveSink {
+    pub fn new(dir: PathBuf, session: &SessionContext) -> Self {
+        let started = session
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = dir
+            .join(session.guild_id.to_string())
+            .join(format!("{}-{}.txt", started, session.session_id));
+
+        Self {
+            path,
+            file: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl TranscriptSink for ArchiveSink {
+    fn name(&self) -> &'static str {
+        "archive"
+    }
+
+    async fn deliver(&self, transcript: &Transcript) -> Result<(), String> {
+        let mut file = self.file.lock().await;
+
+        if file.is_none() {
+            if let Some(parent) = self.path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| format!("{}: {}", parent.display(), e))?;
+            }
+            let opened = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.path)
+                .await
+                .map_err(|e| format!("{}: {}", self.path.display(), e))?;
+            *file = Some(BufWriter::new(opened));
+        }
+
+        let offset = transcript.offset().as_secs();
+        let mut line = format!(
+            "[{:02}:{:02}:{:02}] {}: {}\n",
+            offset / 3600,
+            (offset % 3600) / 60,
+            offset % 60,
+            transcript
+                .user_id
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| format!("ssrc:{}", transcript.ssrc)),
+            transcript.text
+        );
+        for translation in &transcript.translations {
+            line.push_str(&format!(
+                "           [{}] {}\n",
+                translation.language, translation.text
+            ));
+        }
+
+        if let Some(writer) = file.as_mut() {
+            writer
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&self) -> Result<(), String> {
+        if let Some(writer) = self.file.lock().await.as_mut() {
+            writer.flush().await.map_err(|e| e.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Escapes characters Discord would treat as formatting, such as the asterisks in masked words.
+pub fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '~' | '`' | '|') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Splits text into chunks of at most `max_len` bytes on whitespace where possible, keeping
+/// line breaks. Words longer than `max_len` are cut at a character boundary.
+fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+
+    for (line_index, line) in text.lines().enumerate() {
+        for (word_index, word) in line.split_whitespace().enumerate() {
+            for (piece_index, piece) in split_word(word, max_len).into_iter().enumerate() {
+                // The rest of a cut word always starts a new chunk
+                let full = piece_index > 0 || current.len() + 1 + piece.len() > max_len;
+                if !current.is_empty() && full {
+                    chunks.push(std::mem::take(&mut current));
+                }
+                if !current.is_empty() {
+                    let new_line = word_index == 0 && line_index > 0;
+                    current.push(if new_line { '\n' } else { ' ' });
+                }
+                current.push_str(piece);
+            }
+        }
+    }
+
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+
+    chunks
+}
+
+/// Cuts `word` into pieces of at most `max_len` bytes, never inside a character.
+fn split_word(word: &str, max_len: usize) -> Vec<&str> {
+    let mut pieces = Vec::new();
+    let mut rest = word;
+    while rest.len() > max_len {
+        let mut end = max_len;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            // A single character wider than `max_len`
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        pieces.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    pieces.push(rest);
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_whitespace_and_keeps_line_breaks() {
+        assert_eq!(
+            split_message("one two three\nfour", 10),
+            ["one two", "three\nfour"]
+        );
+        assert!(split_message("  \n ", 10).is_empty());
+    }
+
+    #[test]
+    fn hard_splits_long_words() {
+        assert_eq!(
+            split_message("hi abcdefghij xy", 4),
+            ["hi", "abcd", "efgh", "ij", "xy"]
+        );
+        // "é" is two bytes, so no cut lands inside it
+        let chunks = split_message("aéééé", 4);
+        assert_eq!(chunks, ["aé", "éé", "é"]);
+        assert!(chunks.iter().all(|chunk| chunk.len() <= 4));
+    }
+}
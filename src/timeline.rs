@@ -0,0 +1,171 @@
+use std::time::Duration;
+
+/// Discord voice runs a 48 kHz RTP clock with 20 ms packets, and songbird ticks once per packet.
+pub const SAMPLE_RATE: u64 = 48_000;
+pub const FRAMES_PER_TICK: u64 = 960;
+/// Longer RTP jumps are treated as a restarted stream (e.g. the client reconnected) rather
+/// than filled with silence.
+const MAX_GAP_FRAMES: u32 = 5 * SAMPLE_RATE as u32;
+
+/// Converts 48 kHz frames on the session timeline to time since the timeline started.
+pub fn frames_to_duration(frames: u64) -> Duration {
+    Duration::from_micros(frames * 1_000_000 / SAMPLE_RATE)
+}
+
+pub fn duration_to_frames(duration: Duration) -> u64 {
+    duration.as_micros() as u64 * SAMPLE_RATE / 1_000_000
+}
+
+/// What was missing before a packet, according to its RTP header.
+#[derive(Default)]
+pub struct Gap {
+    pub lost_packets: u64,
+    /// Frames of audio the sender produced that never reached us.
+    pub missing_frames: u64,
+}
+
+/// Where one speaker's audio sits on the session timeline.
+///
+/// The position counts 48 kHz frames since the session's first voice tick. It advances by
+/// every frame fed to the recognizer, audio or inserted silence, so utterance start times
+/// stay sample-accurate even when packets are lost or the speaker pauses.
+#[derive(Default)]
+pub struct SpeakerTimeline {
+    position: u64,
+    next_sequence: Option<u16>,
+    next_timestamp: Option<u32>,
+}
+
+impl SpeakerTimeline {
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Moves a speaker that has fallen behind, e.g. while not speaking, up to `session_frame`.
+    pub fn sync(&mut self, session_frame: u64) {
+        self.position = self.position.max(session_frame);
+    }
+
+    /// Checks a packet's sequence number and timestamp against the previous packet.
+    ///
+    /// Packets that arrive late or duplicated report no gap; their audio was already
+    /// accounted for with concealment or silence.
+    pub fn on_packet(&mut self, sequence: u16, timestamp: u32) -> Gap {
+        let mut gap = Gap::default();
+
+        match self.next_sequence {
+            Some(expected) if sequence.wrapping_sub(expected) < u16::MAX / 2 => {
+                gap.lost_packets = u64::from(sequence.wrapping_sub(expected));
+                self.next_sequence = Some(sequence.wrapping_add(1));
+            }
+            Some(_) => {}
+            None => self.next_sequence = Some(sequence.wrapping_add(1)),
+        }
+
+        match self.next_timestamp {
+            Some(expected) if timestamp.wrapping_sub(expected) <= MAX_GAP_FRAMES => {
+                gap.missing_frames = u64::from(timestamp.wrapping_sub(expected));
+                self.next_timestamp = Some(timestamp);
+            }
+            // Late, or we already filled past it with silence; keep the clock moving forward
+            Some(expected) if expected.wrapping_sub(timestamp) <= MAX_GAP_FRAMES => {}
+            _ => self.next_timestamp = Some(timestamp),
+        }
+
+        gap
+    }
+
+    /// Records `frames` of audio or silence appended to the speaker's stream.
+    pub fn advance(&mut self, frames: u64) {
+        self.position += frames;
+        if let Some(timestamp) = &mut self.next_timestamp {
+            *timestamp = timestamp.wrapping_add(frames as u32);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gap(gap: Gap) -> (u64, u64) {
+        (gap.lost_packets, gap.missing_frames)
+    }
+
+    #[test]
+    fn counts_gaps_across_wraparound() {
+        let mut timeline = SpeakerTimeline::default();
+        let start = u32::MAX - 959;
+        assert_eq!(gap(timeline.on_packet(u16::MAX, start)), (0, 0));
+        timeline.advance(FRAMES_PER_TICK);
+
+        // Both the sequence number and the timestamp wrap to 0
+        assert_eq!(gap(timeline.on_packet(0, 0)), (0, 0));
+        timeline.advance(FRAMES_PER_TICK);
+
+        // Packet 1 was lost
+        assert_eq!(gap(timeline.on_packet(2, 1920)), (1, 960));
+        timeline.advance(FRAMES_PER_TICK);
+        assert_eq!(timeline.position(), 3 * FRAMES_PER_TICK);
+    }
+
+    #[test]
+    fn ignores_late_packets() {
+        let mut timeline = SpeakerTimeline::default();
+        timeline.on_packet(10, 9600);
+        timeline.advance(FRAMES_PER_TICK);
+        timeline.on_packet(12, 11_520);
+        timeline.advance(FRAMES_PER_TICK);
+
+        assert_eq!(gap(timeline.on_packet(11, 10_560)), (0, 0));
+        assert_eq!(gap(timeline.on_packet(13, 12_480)), (0, 0));
+    }
+
+    #[test]
+    fn restarts_after_a_long_jump() {
+        let mut timeline = SpeakerTimeline::default();
+        timeline.on_packet(1, 0);
+        timeline.advance(FRAMES_PER_TICK);
+
+        assert_eq!(gap(timeline.on_packet(2, 60 * SAMPLE_RATE as u32)), (0, 0));
+        timeline.advance(FRAMES_PER_TICK);
+        assert_eq!(
+            gap(timeline.on_packet(3, 60 * SAMPLE_RATE as u32 + 960)),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn sync_only_moves_forward() {
+        let mut timeline = SpeakerTimeline::default();
+        timeline.advance(500);
+        timeline.sync(200);
+        assert_eq!(timeline.position(), 500);
+        timeline.sync(1000);
+        assert_eq!(timeline.position(), 1000);
+    }
+
+    #[test]
+    fn converts_frames_to_durations() {
+        assert_eq!(
+            frames_to_duration(FRAMES_PER_TICK),
+            Duration::from_millis(20)
+        );
+        assert_eq!(
+            frames_to_duration(SAMPLE_RATE * 90),
+            Duration::from_secs(90)
+        );
+    }
+
+    #[test]
+    fn converts_durations_to_frames() {
+        assert_eq!(
+            duration_to_frames(Duration::from_millis(20)),
+            FRAMES_PER_TICK
+        );
+        assert_eq!(
+            duration_to_frames(Duration::from_secs(90)),
+            SAMPLE_RATE * 90
+        );
+    }
+}
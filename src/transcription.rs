@@ -1,53 +1,214 @@
+use crate::endpoint::Endpointer;
+use crate::guild_settings::TranscriptionMode;
+use crate::live_stream::{LiveEvent, LiveStream};
+use crate::metrics::{SessionGauge, METRICS};
+use crate::mixer::{self, Attribution, Segment};
+use crate::pipeline::{self, Pipeline, StageKind};
+use crate::postprocess::ProfanityFilter;
+use crate::recognizer_pool::{AcquireError, PooledRecognizer, RecognizerPool, TARGET_SAMPLE_RATE};
+use crate::sink::{SessionContext, Transcript, TranscriptSender};
+use crate::timeline::{self, SpeakerTimeline, FRAMES_PER_TICK};
 use serenity::async_trait;
+use serenity::model::id::UserId;
+use songbird::events::context_data::VoiceTick;
 use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
-use vosk::{Model, Recognizer};
-
-const TARGET_SAMPLE_RATE: f32 = 16000.0;
-const BUFFER_SIZE: usize = 1600; // 100ms at 16kHz
-const SILENCE_TIMEOUT: Duration = Duration::from_millis(1500); // 1.5 seconds of silence
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{debug, debug_span, error, info, trace, warn, Span};
+use vosk::DecodingState;
+
+/// Key of the shared recognizer's state in mixed mode.
+const MIXED_SSRC: u32 = 0;
+/// How far before the maximum utterance length a split point is looked for.
+const SPLIT_WINDOW: Duration = Duration::from_secs(5);
+
+fn silence(frames: u64) -> Vec<i16> {
+    vec![0; frames as usize * 2]
+}
 
 struct UserAudioState {
-    recognizer: Recognizer,
+    recognizer: PooledRecognizer,
     is_speaking: bool,
+    last_active: Instant,
     accumulated_text: String,
-    audio_buffer: Vec<i16>,
+    /// Takes decoded voice to recognizer input.
+    pipeline: Pipeline,
     last_audio_time: Option<Instant>,
+    /// Whether Vosk has detected the end of a phrase since the last audio.
+    at_endpoint: bool,
+    endpointer: Endpointer,
+    utterance_started: Option<SystemTime>,
+    /// Timeline frame the current utterance started at.
+    utterance_frame: u64,
+    /// Places the current utterance could be split, once it nears the maximum length.
+    split_points: Vec<SplitPoint>,
+    timeline: SpeakerTimeline,
+    /// Mixed mode only: who said which part of the text.
+    attribution: Option<Attribution>,
+}
+
+/// A boundary between two pieces of recognized text.
+struct SplitPoint {
+    /// Level of the audio just before it.
+    rms: f32,
+    /// Length of the utterance's text up to it.
+    text_len: usize,
+    position: u64,
+}
+
+impl Drop for UserAudioState {
+    fn drop(&mut self) {
+        METRICS.tracked_ssrcs.dec();
+    }
+}
+
+/// How a session's audio is transcribed, from the bot's configuration and guild settings.
+#[derive(Clone)]
+pub struct ReceiverOptions {
+    pub idle_timeout: Duration,
+    pub mode: TranscriptionMode,
+    pub stages: Vec<StageKind>,
+    /// Longer utterances are split, so an open mic with no pauses still produces transcripts.
+    pub max_utterance: Duration,
+    /// Bounds on the silence that ends an utterance; each speaker's is learned in between.
+    pub min_silence: Duration,
+    pub max_silence: Duration,
+    /// The profanity filter of the post-processing chain, if it masks or removes words.
+    /// Applied to live partials, which don't go through the chain.
+    pub profanity: Option<ProfanityFilter>,
 }
 
 #[derive(Clone)]
 pub struct Receiver {
-    model: Arc<Model>,
+    pool: Arc<RecognizerPool>,
+    options: ReceiverOptions,
+    context: SessionContext,
+    transcripts: TranscriptSender,
+    live: LiveStream,
     audio_states: Arc<Mutex<HashMap<u32, UserAudioState>>>,
+    speakers: Arc<Mutex<HashMap<u32, UserId>>>,
+    /// Speakers refused a recognizer at the cap, until they stop speaking.
+    rejected: Arc<Mutex<HashSet<u32>>>,
+    driver_connected: Arc<AtomicBool>,
+    recent_latency: Arc<Mutex<Option<Duration>>>,
+    /// Voice ticks since the session started; each is 20 ms on the session timeline.
+    ticks: Arc<AtomicU64>,
+    timeline_origin: Arc<OnceLock<SystemTime>>,
+    span: Span,
+    _session: Arc<SessionGauge>,
 }
 
 impl Receiver {
-    pub fn new(model: Arc<Model>) -> Self {
+    pub fn new(
+        pool: Arc<RecognizerPool>,
+        options: ReceiverOptions,
+        context: SessionContext,
+        transcripts: TranscriptSender,
+        live: LiveStream,
+        span: Span,
+    ) -> Self {
         Self {
-            model,
+            pool,
+            options,
+            context,
+            transcripts,
+            live,
             audio_states: Arc::new(Mutex::new(HashMap::new())),
+            speakers: Arc::new(Mutex::new(HashMap::new())),
+            rejected: Arc::new(Mutex::new(HashSet::new())),
+            driver_connected: Arc::new(AtomicBool::new(true)),
+            recent_latency: Arc::new(Mutex::new(None)),
+            ticks: Arc::new(AtomicU64::new(0)),
+            timeline_origin: Arc::new(OnceLock::new()),
+            span,
+            _session: Arc::new(SessionGauge::start()),
         }
     }
 
-    fn get_or_create_state(&self, ssrc: u32) -> Result<(), String> {
+    pub fn tracked_speakers(&self) -> usize {
+        self.audio_states.lock().unwrap().len()
+    }
+
+    pub fn driver_connected(&self) -> bool {
+        self.driver_connected.load(Ordering::Relaxed)
+    }
+
+    /// End-of-speech to transcript latency of the most recent utterance.
+    pub fn recent_latency(&self) -> Option<Duration> {
+        *self.recent_latency.lock().unwrap()
+    }
+
+    /// Span for events about a single speaker, nested under the session span.
+    fn speaker_span(&self, ssrc: u32) -> Span {
+        let user_id = self.speakers.lock().unwrap().get(&ssrc).map(|u| u.get());
+        debug_span!(parent: &self.span, "speaker", ssrc, user_id)
+    }
+
+    fn get_or_create_state(&self, ssrc: u32) -> Result<(), AcquireError> {
+        let result = self.try_get_or_create_state(ssrc);
+        let mut rejected = self.rejected.lock().unwrap();
+        match result {
+            // Counted once per speaker until they stop, not on every voice tick
+            Err(AcquireError::Exhausted) => {
+                if rejected.insert(ssrc) {
+                    METRICS.recognizer_cap_rejections.inc();
+                }
+            }
+            _ => {
+                rejected.remove(&ssrc);
+            }
+        }
+        result
+    }
+
+    fn try_get_or_create_state(&self, ssrc: u32) -> Result<(), AcquireError> {
         let mut states = self.audio_states.lock().unwrap();
 
-        if !states.contains_key(&ssrc) {
-            let recognizer = Recognizer::new(&self.model, TARGET_SAMPLE_RATE).ok_or(format!(
-                "Failed to create Vosk recognizer for SSRC {}",
-                ssrc
-            ))?;
+        if let Some(state) = states.get_mut(&ssrc) {
+            state.last_active = Instant::now();
+        } else {
+            let recognizer = match self.pool.acquire() {
+                Err(AcquireError::Exhausted) => {
+                    // At the cap: take over the recognizer of this session's longest-idle
+                    // speaker that has nothing pending
+                    let victim = states
+                        .iter()
+                        .filter(|(_, state)| !state.is_speaking && state.last_audio_time.is_none())
+                        .min_by_key(|(_, state)| state.last_active)
+                        .map(|(ssrc, _)| *ssrc)
+                        .ok_or(AcquireError::Exhausted)?;
+                    debug!(
+                        evicted_ssrc = victim,
+                        "Reclaiming recognizer from idle speaker"
+                    );
+                    states.remove(&victim);
+                    METRICS.evicted_speakers.inc();
+                    self.pool.acquire()?
+                }
+                result => result?,
+            };
 
+            METRICS.tracked_ssrcs.inc();
             states.insert(
                 ssrc,
                 UserAudioState {
                     recognizer,
                     is_speaking: false,
+                    last_active: Instant::now(),
                     accumulated_text: String::new(),
-                    audio_buffer: Vec::with_capacity(BUFFER_SIZE),
+                    // The stages were checked when the configuration was loaded
+                    pipeline: Pipeline::build(&self.options.stages).expect("valid audio pipeline"),
                     last_audio_time: None,
+                    at_endpoint: false,
+                    endpointer: Endpointer::new(self.options.min_silence, self.options.max_silence),
+                    utterance_started: None,
+                    utterance_frame: 0,
+                    split_points: Vec::new(),
+                    timeline: SpeakerTimeline::default(),
+                    attribution: (self.options.mode == TranscriptionMode::Mixed)
+                        .then(Attribution::default),
                 },
             );
         }
@@ -55,84 +216,334 @@ impl Receiver {
         Ok(())
     }
 
-    fn stereo_to_mono(stereo: &[i16]) -> Vec<i16> {
-        stereo
-            .chunks_exact(2)
-            .map(|chunk| ((chunk[0] as i32 + chunk[1] as i32) / 2) as i16)
-            .collect()
+    /// Wall-clock time of a frame on the session timeline.
+    fn timeline_time(&self, frames: u64) -> SystemTime {
+        let origin = self
+            .timeline_origin
+            .get()
+            .copied()
+            .unwrap_or(self.context.started_at);
+        origin + timeline::frames_to_duration(frames)
     }
 
-    fn resample_48k_to_16k(samples: &[i16]) -> Vec<i16> {
-        samples.iter().step_by(3).copied().collect()
+    /// Feeds one tick of a speaker's audio, or silence if the packet was lost and couldn't be
+    /// concealed, filling any gap the RTP header reveals before it.
+    fn process_audio(
+        &self,
+        ssrc: u32,
+        session_frame: u64,
+        rtp: Option<(u16, u32)>,
+        audio_data: Option<&[i16]>,
+    ) {
+        let mut states = self.audio_states.lock().unwrap();
+
+        if let Some(state) = states.get_mut(&ssrc) {
+            Self::on_audio(state);
+
+            let gap = rtp
+                .map(|(sequence, timestamp)| state.timeline.on_packet(sequence, timestamp))
+                .unwrap_or_default();
+            if gap.lost_packets > 0 {
+                trace!(lost = gap.lost_packets, "Packets lost");
+                METRICS.lost_packets.inc_by(gap.lost_packets);
+            }
+
+            if state.utterance_started.is_none() {
+                // Anything before the first frame of an utterance isn't part of it
+                state.timeline.sync(session_frame);
+                state.utterance_frame = state.timeline.position();
+                state.utterance_started = Some(self.timeline_time(state.utterance_frame));
+            } else if gap.missing_frames > 0 {
+                trace!(
+                    frames = gap.missing_frames,
+                    "Filling audio gap with silence"
+                );
+                METRICS
+                    .gap_silence_seconds
+                    .inc_by(gap.missing_frames as f64 / timeline::SAMPLE_RATE as f64);
+                self.feed(ssrc, state, &silence(gap.missing_frames));
+            }
+
+            match audio_data {
+                Some(audio_data) => self.feed(ssrc, state, audio_data),
+                None => {
+                    METRICS
+                        .gap_silence_seconds
+                        .inc_by(FRAMES_PER_TICK as f64 / timeline::SAMPLE_RATE as f64);
+                    self.feed(ssrc, state, &silence(FRAMES_PER_TICK));
+                }
+            }
+        }
+    }
+
+    /// Notes that a speaker's audio arrived, learning from the pause before it if it was
+    /// within an utterance.
+    fn on_audio(state: &mut UserAudioState) {
+        let now = Instant::now();
+        if let Some(last) = state.last_audio_time {
+            state.endpointer.on_pause(now.duration_since(last));
+        }
+        state.last_audio_time = Some(now);
+        state.last_active = now;
+        state.at_endpoint = false;
+    }
+
+    /// Mixed mode: feeds one tick of everyone's audio to the session's shared recognizer.
+    fn process_mixed(&self, session_frame: u64, frames: &[(u32, &[i16])]) {
+        let mut states = self.audio_states.lock().unwrap();
+
+        if let Some(state) = states.get_mut(&MIXED_SSRC) {
+            // Nobody's speaking update ends a shared utterance; the silence timeout does
+            state.is_speaking = true;
+            Self::on_audio(state);
+            if state.utterance_started.is_none() {
+                state.timeline.sync(session_frame);
+                state.utterance_frame = state.timeline.position();
+                state.utterance_started = Some(self.timeline_time(state.utterance_frame));
+            }
+
+            let position = state.timeline.position();
+            if let Some(attribution) = &mut state.attribution {
+                for (ssrc, frame) in frames {
+                    attribution.add_energy(*ssrc, frame, position);
+                }
+            }
+            let mixed = mixer::mix(frames.iter().map(|(_, frame)| *frame));
+            self.feed(MIXED_SSRC, state, &mixed);
+        }
     }
 
-    fn process_audio(&self, ssrc: u32, audio_data: &[i16]) -> Result<(), String> {
+    /// Keeps a pause inside an utterance on the timeline, so words either side of it aren't
+    /// run together. Doesn't count as audio for the silence timeout.
+    fn process_silence(&self, ssrc: u32) {
+        self.rejected.lock().unwrap().remove(&ssrc);
         let mut states = self.audio_states.lock().unwrap();
 
         if let Some(state) = states.get_mut(&ssrc) {
-            // Update last audio time
-            state.last_audio_time = Some(Instant::now());
-            
-            // Audio from VoiceTick is already 48kHz stereo PCM
-            let mono = Self::stereo_to_mono(audio_data);
-            let resampled = Self::resample_48k_to_16k(&mono);
-            println!("[DEBUG] Audio processing - SSRC: {}, stereo: {} samples, mono: {} samples, resampled: {} samples", 
-                     ssrc, audio_data.len(), mono.len(), resampled.len());
-
-            // Add resampled audio to buffer
-            state.audio_buffer.extend_from_slice(&resampled);
-            println!(
-                "[DEBUG] Buffer size for SSRC {}: {} samples",
-                ssrc,
-                state.audio_buffer.len()
-            );
+            if state.utterance_started.is_some() {
+                self.feed(ssrc, state, &silence(FRAMES_PER_TICK));
+            }
+        }
+    }
 
-            // Process buffer when we have enough samples
-            if state.audio_buffer.len() >= BUFFER_SIZE {
-                println!(
-                    "[DEBUG] Processing buffer for SSRC {} ({} samples)",
-                    ssrc,
-                    state.audio_buffer.len()
-                );
+    /// Appends 48 kHz stereo PCM to a speaker's stream, running the recognizer on whatever
+    /// comes out of the audio pipeline.
+    fn feed(&self, ssrc: u32, state: &mut UserAudioState, audio_data: &[i16]) {
+        state.timeline.advance(audio_data.len() as u64 / 2);
+
+        let samples = state.pipeline.process(audio_data.to_vec());
+        if !samples.is_empty() {
+            trace!(samples = samples.len(), "Processing audio");
+
+            let started = Instant::now();
+            let accepted = state.recognizer.accept_waveform(&samples);
+            let elapsed = started.elapsed().as_secs_f64();
+            let audio_secs = samples.len() as f64 / TARGET_SAMPLE_RATE as f64;
+            METRICS.accept_waveform_seconds.observe(elapsed);
+            METRICS.real_time_factor.observe(elapsed / audio_secs);
+
+            if let Ok(DecodingState::Finalized) = accepted {
+                state.at_endpoint = true;
+            }
+            let text_len = state.accumulated_text.len();
+            if accepted.is_ok() {
+                let result = state.recognizer.result();
+                if let Some(single) = result.single() {
+                    let text = single.text.trim();
+                    if !text.is_empty() {
+                        debug!(text, "Partial result");
+                        if !state.accumulated_text.is_empty() {
+                            state.accumulated_text.push(' ');
+                        }
+                        state.accumulated_text.push_str(text);
 
-                if state
-                    .recognizer
-                    .accept_waveform(&state.audio_buffer)
-                    .is_ok()
-                {
-                    let result = state.recognizer.result();
-                    if let Some(single) = result.single() {
-                        let text = single.text.trim();
-                        if !text.is_empty() {
-                            println!("[VOSK] Partial result for SSRC {}: {}", ssrc, text);
-                            if !state.accumulated_text.is_empty() {
-                                state.accumulated_text.push(' ');
+                        if let Some(attribution) = &mut state.attribution {
+                            attribution.attribute(text);
+                        }
+                        if self.live.has_subscribers() {
+                            // In mixed mode, the partial is the current speaker's segment
+                            let (ssrc, text, started_at) = match state
+                                .attribution
+                                .as_ref()
+                                .and_then(Attribution::current_segment)
+                            {
+                                Some(segment) => (
+                                    segment.ssrc,
+                                    segment.text.clone(),
+                                    self.timeline_time(segment.start),
+                                ),
+                                None => (
+                                    ssrc,
+                                    state.accumulated_text.clone(),
+                                    state.utterance_started.unwrap_or_else(SystemTime::now),
+                                ),
+                            };
+                            let text = match &self.options.profanity {
+                                Some(filter) => filter.apply(&text),
+                                None => text,
+                            };
+                            if !text.trim().is_empty() {
+                                self.live.publish(LiveEvent::partial(
+                                    &self.context,
+                                    ssrc,
+                                    self.speakers.lock().unwrap().get(&ssrc).copied(),
+                                    text,
+                                    started_at,
+                                ));
                             }
-                            state.accumulated_text.push_str(text);
                         }
                     }
                 }
-
-                // Clear buffer after processing
-                state.audio_buffer.clear();
+            } else {
+                warn!("Recognizer rejected audio buffer");
+                METRICS.dropped_frames.inc();
             }
+
+            let committed = state.accumulated_text.len() != text_len;
+            self.consider_split(ssrc, state, pipeline::rms(&samples), committed);
         }
+    }
 
-        Ok(())
+    /// Records where the recognizer finished a phrase once an utterance nears the maximum
+    /// length, and splits the utterance when it gets there.
+    ///
+    /// Vosk only hands over text at the end of a phrase, so those are the only points where
+    /// everything said before is in `accumulated_text` and nothing said after is.
+    fn consider_split(&self, ssrc: u32, state: &mut UserAudioState, rms: f32, committed: bool) {
+        if state.utterance_started.is_none() {
+            return;
+        }
+        let max = timeline::duration_to_frames(self.options.max_utterance);
+        let window = timeline::duration_to_frames(SPLIT_WINDOW).min(max / 2);
+        let length = state
+            .timeline
+            .position()
+            .saturating_sub(state.utterance_frame);
+        if length + window < max {
+            return;
+        }
+
+        if committed {
+            state.split_points.push(SplitPoint {
+                rms,
+                text_len: state.accumulated_text.len(),
+                position: state.timeline.position(),
+            });
+        }
+        if length >= max {
+            self.split_utterance(ssrc, state);
+        }
+    }
+
+    /// Ends the current utterance at the quietest recent phrase end, or right now if there
+    /// wasn't one, without losing or repeating words.
+    fn split_utterance(&self, ssrc: u32, state: &mut UserAudioState) {
+        let split_points = std::mem::take(&mut state.split_points);
+        let quietest = split_points
+            .iter()
+            .min_by(|a, b| a.rms.total_cmp(&b.rms))
+            // Mixed-mode text has already been handed to speakers' segments
+            .filter(|_| state.attribution.is_none());
+        METRICS.utterance_splits.inc();
+
+        let (text_len, position) = match quietest {
+            Some(split) => {
+                debug!(
+                    rms = split.rms,
+                    "Maximum utterance length reached, splitting"
+                );
+                (split.text_len, split.position)
+            }
+            None => {
+                // No phrase ended nearby; make the recognizer finish the one in progress
+                debug!("Maximum utterance length reached without a pause, splitting");
+                let result = state.recognizer.final_result();
+                let text = result
+                    .single()
+                    .map(|single| single.text.trim().to_string())
+                    .unwrap_or_default();
+                state.recognizer.reset();
+                if let Some(attribution) = &mut state.attribution {
+                    attribution.attribute(&text);
+                }
+                if !text.is_empty() && !state.accumulated_text.is_empty() {
+                    state.accumulated_text.push(' ');
+                }
+                state.accumulated_text.push_str(&text);
+                (state.accumulated_text.len(), state.timeline.position())
+            }
+        };
+
+        let started_at = state
+            .utterance_started
+            .replace(self.timeline_time(position))
+            .unwrap_or_else(SystemTime::now);
+        state.utterance_frame = position;
+
+        let utterances = match &mut state.attribution {
+            Some(attribution) => {
+                state.accumulated_text.clear();
+                self.segment_utterances(attribution.take_segments())
+            }
+            None => {
+                let rest = state.accumulated_text.split_off(text_len);
+                let text =
+                    std::mem::replace(&mut state.accumulated_text, rest.trim_start().to_string());
+                let text = text.trim().to_string();
+                if text.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![(ssrc, text, started_at)]
+                }
+            }
+        };
+        self.emit(utterances);
+    }
+
+    fn segment_utterances(&self, segments: Vec<Segment>) -> Vec<(u32, String, SystemTime)> {
+        segments
+            .into_iter()
+            .map(|segment| {
+                (
+                    segment.ssrc,
+                    segment.text,
+                    self.timeline_time(segment.start),
+                )
+            })
+            .collect()
+    }
+
+    /// Sends finished utterances to the session's transcript outputs.
+    fn emit(&self, utterances: Vec<(u32, String, SystemTime)>) {
+        for (ssrc, text, started_at) in utterances {
+            METRICS.utterances.inc();
+            METRICS.words.inc_by(text.split_whitespace().count() as u64);
+
+            info!(ssrc, text = %text, "Final transcription");
+            self.transcripts.send(Transcript {
+                session: self.context,
+                ssrc,
+                user_id: self.speakers.lock().unwrap().get(&ssrc).copied(),
+                text: text.clone(),
+                raw_text: text,
+                translations: Vec::new(),
+                started_at,
+            });
+        }
     }
 
     fn finalize_transcription(&self, ssrc: u32) -> Option<String> {
         let mut states = self.audio_states.lock().unwrap();
 
         if let Some(state) = states.get_mut(&ssrc) {
-            // Process any remaining audio in buffer
-            if !state.audio_buffer.is_empty() {
-                println!(
-                    "[DEBUG] Finalizing with remaining buffer: {} samples",
-                    state.audio_buffer.len()
-                );
-                let _ = state.recognizer.accept_waveform(&state.audio_buffer);
-                state.audio_buffer.clear();
+            let end_of_speech = state.last_audio_time;
+            let started_at = state.utterance_started.take();
+
+            // Process any audio the pipeline is still holding
+            let remaining = state.pipeline.flush();
+            if !remaining.is_empty() {
+                trace!(samples = remaining.len(), "Finalizing with remaining audio");
+                let _ = state.recognizer.accept_waveform(&remaining);
             }
 
             let result = state.recognizer.final_result();
@@ -143,6 +554,15 @@ impl Receiver {
                 String::new()
             };
 
+            // In mixed mode each speaker's part becomes its own utterance
+            let utterances: Vec<(u32, String, SystemTime)> = match &mut state.attribution {
+                Some(attribution) => {
+                    attribution.attribute(&final_text);
+                    self.segment_utterances(attribution.take_segments())
+                }
+                None => Vec::new(),
+            };
+
             let complete_text = if !state.accumulated_text.is_empty() {
                 format!("{} {}", state.accumulated_text, final_text)
                     .trim()
@@ -150,20 +570,33 @@ impl Receiver {
             } else {
                 final_text
             };
+            let utterances = if state.attribution.is_some() || complete_text.is_empty() {
+                utterances
+            } else {
+                vec![(
+                    ssrc,
+                    complete_text.clone(),
+                    started_at.unwrap_or_else(SystemTime::now),
+                )]
+            };
 
             state.accumulated_text.clear();
+            state.split_points.clear();
             state.is_speaking = false;
             state.last_audio_time = None;
-
-            if let Some(new_recognizer) = Recognizer::new(&self.model, TARGET_SAMPLE_RATE) {
-                state.recognizer = new_recognizer;
-            }
+            state.at_endpoint = false;
+            state.recognizer.reset();
 
             if !complete_text.is_empty() {
-                println!(
-                    "[VOSK] Final transcription for SSRC {}: {}",
-                    ssrc, complete_text
-                );
+                if let Some(end_of_speech) = end_of_speech {
+                    let latency = end_of_speech.elapsed();
+                    METRICS
+                        .transcript_latency_seconds
+                        .observe(latency.as_secs_f64());
+                    *self.recent_latency.lock().unwrap() = Some(latency);
+                }
+
+                self.emit(utterances);
                 return Some(complete_text);
             }
         }
@@ -171,28 +604,89 @@ impl Receiver {
         None
     }
 
+    fn process_mixed_tick(&self, tick: &VoiceTick, session_frame: u64) {
+        let frames: Vec<(u32, &[i16])> = tick
+            .speaking
+            .iter()
+            .filter_map(|(ssrc, voice_data)| {
+                let decoded = voice_data.decoded_voice.as_deref();
+                Some((*ssrc, decoded.filter(|decoded| !decoded.is_empty())?))
+            })
+            .collect();
+        if frames.is_empty() {
+            self.process_silence(MIXED_SSRC);
+            return;
+        }
+
+        let _span = self.span.enter();
+        // Failures are logged once per utterance on the speaking update
+        if self.get_or_create_state(MIXED_SSRC).is_err() {
+            METRICS.dropped_frames.inc_by(frames.len() as u64);
+            return;
+        }
+        self.process_mixed(session_frame, &frames);
+    }
+
+    /// Finalizes every speaker's pending audio and text, e.g. before the session ends.
+    pub fn finalize_all(&self) {
+        let ssrcs: Vec<u32> = self.audio_states.lock().unwrap().keys().copied().collect();
+
+        for ssrc in ssrcs {
+            let _span = self.speaker_span(ssrc).entered();
+            self.finalize_transcription(ssrc);
+        }
+    }
+
+    pub async fn flush(&self) {
+        self.transcripts.flush().await;
+    }
+
     fn check_silence_timeouts(&self) {
         let now = Instant::now();
-        let mut states = self.audio_states.lock().unwrap();
+        let states = self.audio_states.lock().unwrap();
         let mut ssrcs_to_finalize = Vec::new();
 
         for (ssrc, state) in states.iter() {
             if let Some(last_audio_time) = state.last_audio_time {
-                if state.is_speaking && now.duration_since(last_audio_time) > SILENCE_TIMEOUT {
-                    println!("[DEBUG] Silence timeout detected for SSRC: {}", ssrc);
-                    ssrcs_to_finalize.push(*ssrc);
+                let timeout = state.endpointer.timeout(state.at_endpoint);
+                if state.is_speaking && now.duration_since(last_audio_time) > timeout {
+                    ssrcs_to_finalize.push((*ssrc, timeout));
                 }
             }
         }
 
         drop(states);
 
-        for ssrc in ssrcs_to_finalize {
-            if let Some(text) = self.finalize_transcription(ssrc) {
-                println!("[DEBUG] Auto-finalized transcription: {}", text);
-            }
+        for (ssrc, timeout) in ssrcs_to_finalize {
+            let _span = self.speaker_span(ssrc).entered();
+            debug!(
+                timeout_ms = timeout.as_millis() as u64,
+                "Silence timeout detected"
+            );
+            METRICS
+                .end_of_utterance_timeout_seconds
+                .observe(timeout.as_secs_f64());
+            self.finalize_transcription(ssrc);
         }
     }
+
+    /// Drops speakers that have been quiet for `idle_timeout`, returning their recognizers
+    /// to the pool.
+    fn evict_idle_states(&self) {
+        let mut states = self.audio_states.lock().unwrap();
+        let idle_timeout = self.options.idle_timeout;
+
+        states.retain(|ssrc, state| {
+            let idle = !state.is_speaking
+                && state.last_audio_time.is_none()
+                && state.last_active.elapsed() > idle_timeout;
+            if idle {
+                debug!(ssrc, "Evicting idle speaker");
+                METRICS.evicted_speakers.inc();
+            }
+            !idle
+        });
+    }
 }
 
 #[async_trait]
@@ -203,12 +697,23 @@ impl VoiceEventHandler for Receiver {
                 let ssrc = speaking.ssrc;
                 let is_speaking = !speaking.speaking.is_empty();
 
-                println!(
-                    "[DEBUG] Speaking state update - SSRC: {}, is_speaking: {}",
-                    ssrc, is_speaking
-                );
+                if let Some(user_id) = speaking.user_id {
+                    self.speakers
+                        .lock()
+                        .unwrap()
+                        .insert(ssrc, UserId::new(user_id.0));
+                }
 
-                {
+                let _span = self.speaker_span(ssrc).entered();
+                debug!(is_speaking, "Speaking state update");
+
+                // In mixed mode everyone shares one recognizer, and its utterances end on
+                // the silence timeout rather than when one speaker stops
+                let mixed = self.options.mode == TranscriptionMode::Mixed;
+                if !is_speaking {
+                    self.rejected.lock().unwrap().remove(&ssrc);
+                }
+                if !mixed {
                     let mut states = self.audio_states.lock().unwrap();
                     if let Some(state) = states.get_mut(&ssrc) {
                         state.is_speaking = is_speaking;
@@ -216,47 +721,75 @@ impl VoiceEventHandler for Receiver {
                 }
 
                 if is_speaking {
-                    println!("[DEBUG] User started speaking - SSRC: {}", ssrc);
-                    if let Err(e) = self.get_or_create_state(ssrc) {
-                        eprintln!("Error: {}", e);
-                    }
-                } else {
-                    println!("[DEBUG] User stopped speaking - SSRC: {}", ssrc);
-                    if let Some(text) = self.finalize_transcription(ssrc) {
-                        println!("[DEBUG] Returned transcription: {}", text);
-                    } else {
-                        println!("[DEBUG] No transcription to finalize for SSRC: {}", ssrc);
+                    match self.get_or_create_state(if mixed { MIXED_SSRC } else { ssrc }) {
+                        Ok(()) => {}
+                        Err(AcquireError::Exhausted) => {
+                            warn!("Recognizer limit reached, not transcribing this speaker")
+                        }
+                        Err(AcquireError::CreateFailed) => {
+                            error!("Failed to create Vosk recognizer")
+                        }
                     }
+                } else if !mixed && self.finalize_transcription(ssrc).is_none() {
+                    debug!("No transcription to finalize");
                 }
             }
-            EventContext::ClientDisconnect(_) => {}
-            EventContext::DriverDisconnect { .. } => {}
+            EventContext::ClientDisconnect(disconnect) => {
+                let _span = self.span.enter();
+                debug!(user_id = disconnect.user_id.0, "Client disconnected");
+            }
+            EventContext::DriverConnect(_) | EventContext::DriverReconnect(_) => {
+                let _span = self.span.enter();
+                self.driver_connected.store(true, Ordering::Relaxed);
+                debug!("Voice driver connected");
+            }
+            EventContext::DriverDisconnect(disconnect) => {
+                let _span = self.span.enter();
+                self.driver_connected.store(false, Ordering::Relaxed);
+                warn!(kind = ?disconnect.kind, reason = ?disconnect.reason, "Voice driver disconnected");
+            }
             EventContext::VoiceTick(tick) => {
+                let session_frame = self.ticks.fetch_add(1, Ordering::Relaxed) * FRAMES_PER_TICK;
+                self.timeline_origin.get_or_init(SystemTime::now);
+
                 // Check for silence timeouts first
                 self.check_silence_timeouts();
-                
+                self.evict_idle_states();
+
+                if self.options.mode == TranscriptionMode::Mixed {
+                    self.process_mixed_tick(tick, session_frame);
+                    return None;
+                }
+
                 for (ssrc, voice_data) in tick.speaking.iter() {
                     let ssrc = *ssrc;
+                    let _span = self.speaker_span(ssrc).entered();
 
-                    if let Some(decoded) = &voice_data.decoded_voice {
-                        println!(
-                            "[DEBUG] Processing audio for SSRC: {}, samples: {}",
-                            ssrc,
-                            decoded.len()
-                        );
-
-                        if let Err(e) = self.get_or_create_state(ssrc) {
-                            eprintln!("Error: {}", e);
-                            continue;
-                        }
+                    // Failures are logged once per utterance on the speaking update
+                    if self.get_or_create_state(ssrc).is_err() {
+                        METRICS.dropped_frames.inc();
+                        continue;
+                    }
 
-                        if !decoded.is_empty() {
-                            // decoded_voice is already Vec<i16> from Opus decoder
-                            if let Err(e) = self.process_audio(ssrc, decoded) {
-                                eprintln!("Error processing audio: {}", e);
-                            }
-                        }
+                    let rtp = voice_data.packet.as_ref().map(|packet| {
+                        let rtp = packet.rtp();
+                        (rtp.get_sequence().0 .0, rtp.get_timestamp().0 .0)
+                    });
+                    // decoded_voice is already Vec<i16> from Opus decoder, and includes
+                    // concealment for lost packets where possible
+                    let decoded = voice_data
+                        .decoded_voice
+                        .as_deref()
+                        .filter(|decoded| !decoded.is_empty());
+                    if decoded.is_none() {
+                        trace!("No decoded voice in tick");
+                        METRICS.dropped_frames.inc();
                     }
+                    self.process_audio(ssrc, session_frame, rtp, decoded);
+                }
+
+                for ssrc in tick.silent.iter() {
+                    self.process_silence(*ssrc);
                 }
             }
             _ => {}
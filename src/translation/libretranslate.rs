@@ -0,0 +1,99 @@
+use super::Translator;
+use serde::{Deserialize, Serialize};
+use serenity::async_trait;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Translates through a LibreTranslate-compatible `/translate` endpoint.
+#[derive(Clone)]
+pub struct LibreTranslate {
+    agent: ureq::Agent,
+    url: String,
+    api_key: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Request<'a> {
+    q: &'a str,
+    source: &'a str,
+    target: &'a str,
+    format: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    api_key: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Response {
+    #[serde(rename = "translatedText")]
+    translated_text: Option<String>,
+    error: Option<String>,
+}
+
+impl LibreTranslate {
+    /// `base_url` is the server root, e.g. `http://localhost:5000`.
+    pub fn new(base_url: &str, api_key: Option<String>) -> Self {
+        Self {
+            agent: ureq::AgentBuilder::new().timeout(REQUEST_TIMEOUT).build(),
+            url: format!("{}/translate", base_url.trim_end_matches('/')),
+            api_key,
+        }
+    }
+
+    fn request(&self, text: &str, source: &str, target: &str) -> Result<String, String> {
+        let body = serde_json::to_string(&Request {
+            q: text,
+            source,
+            target,
+            format: "text",
+            api_key: self.api_key.as_deref(),
+        })
+        .map_err(|e| e.to_string())?;
+
+        let response = match self
+            .agent
+            .post(&self.url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+        {
+            Ok(response) => response,
+            // Error responses carry a JSON body with the reason
+            Err(ureq::Error::Status(status, response)) => {
+                let reason = response
+                    .into_string()
+                    .ok()
+                    .and_then(|body| serde_json::from_str::<Response>(&body).ok())
+                    .and_then(|body| body.error)
+                    .unwrap_or_default();
+                return Err(format!("HTTP {} {}", status, reason));
+            }
+            Err(e) => return Err(e.to_string()),
+        };
+
+        let body = response.into_string().map_err(|e| e.to_string())?;
+        let parsed: Response =
+            serde_json::from_str(&body).map_err(|e| format!("invalid response: {}", e))?;
+        match (parsed.translated_text, parsed.error) {
+            (Some(translated), _) => Ok(translated),
+            (None, Some(error)) => Err(error),
+            (None, None) => Err(String::from("response has no translatedText")),
+        }
+    }
+}
+
+#[async_trait]
+impl Translator for LibreTranslate {
+    fn name(&self) -> &'static str {
+        "libretranslate"
+    }
+
+    async fn translate(&self, text: &str, source: &str, target: &str) -> Result<String, String> {
+        // ureq is blocking, so keep it off the async workers
+        let translator = self.clone();
+        let (text, source, target) = (text.to_string(), source.to_string(), target.to_string());
+
+        tokio::task::spawn_blocking(move || translator.request(&text, &source, &target))
+            .await
+            .map_err(|e| e.to_string())?
+    }
+}
@@ -0,0 +1,299 @@
+mod libretranslate;
+
+use crate::metrics::METRICS;
+use futures::future::join_all;
+use serde::Serialize;
+use serenity::async_trait;
+use serenity::prelude::TypeMapKey;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+pub use libretranslate::LibreTranslate;
+
+const CACHE_CAPACITY: usize = 1024;
+/// All of a caption's translations must arrive within this, so a slow server holds back
+/// delivery by at most this long.
+const DEADLINE: Duration = Duration::from_secs(5);
+/// After a failure, uncached translations are skipped for this long so a struggling server
+/// doesn't delay every caption.
+const FAILURE_BACKOFF: Duration = Duration::from_secs(10);
+
+#[async_trait]
+pub trait Translator: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn translate(&self, text: &str, source: &str, target: &str) -> Result<String, String>;
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Translation {
+    pub language: String,
+    pub text: String,
+}
+
+type CacheKey = (String, String, String);
+
+/// Wraps a `Translator` with a bounded cache shared by all sessions.
+///
+/// Repeated phrases ("yeah", "okay", "thank you") make up a good share of captions, so
+/// caching saves a round trip for many of them.
+pub struct TranslationService {
+    translator: Box<dyn Translator>,
+    cache: Mutex<TranslationCache>,
+    /// When the translator may be tried again after a failure.
+    retry_at: Mutex<Option<Instant>>,
+}
+
+#[derive(Default)]
+struct TranslationCache {
+    entries: HashMap<CacheKey, String>,
+    order: VecDeque<CacheKey>,
+}
+
+impl TranslationService {
+    pub fn new(translator: Box<dyn Translator>) -> Arc<Self> {
+        Arc::new(Self {
+            translator,
+            cache: Mutex::new(TranslationCache::default()),
+            retry_at: Mutex::new(None),
+        })
+    }
+
+    /// Translates `text` into each target language, all at once and within one deadline.
+    ///
+    /// Targets that fail are left out, so the caption falls back to the original text alone.
+    pub async fn translate_all(
+        &self,
+        text: &str,
+        source: &str,
+        targets: &[String],
+    ) -> Vec<Translation> {
+        let deadline = tokio::time::Instant::now() + DEADLINE;
+        let requests = targets
+            .iter()
+            .filter(|target| target.as_str() != source)
+            .map(|target| self.translate_one(text, source, target, deadline));
+
+        join_all(requests).await.into_iter().flatten().collect()
+    }
+
+    async fn translate_one(
+        &self,
+        text: &str,
+        source: &str,
+        target: &str,
+        deadline: tokio::time::Instant,
+    ) -> Option<Translation> {
+        let key = (source.to_string(), target.to_string(), text.to_string());
+        let cached = self.cache.lock().unwrap().entries.get(&key).cloned();
+        if let Some(translated) = cached {
+            METRICS.translation_cache_hits.inc();
+            return Some(Translation {
+                language: target.to_string(),
+                text: translated,
+            });
+        }
+
+        if self.backing_off() {
+            debug!(target = %target, "Skipping translation after a recent failure");
+            return None;
+        }
+
+        let request = self.translator.translate(text, source, target);
+        let result = match tokio::time::timeout_at(deadline, request).await {
+            Ok(result) => result,
+            Err(_) => Err(format!("timed out after {:?}", DEADLINE)),
+        };
+        match result {
+            Ok(translated) => {
+                self.insert(key, translated.clone());
+                Some(Translation {
+                    language: target.to_string(),
+                    text: translated,
+                })
+            }
+            Err(e) => {
+                METRICS.translation_failures.inc();
+                *self.retry_at.lock().unwrap() = Some(Instant::now() + FAILURE_BACKOFF);
+                warn!(
+                    translator = self.translator.name(),
+                    target = %target,
+                    "Translation failed, posting original text only: {}",
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    fn backing_off(&self) -> bool {
+        self.retry_at
+            .lock()
+            .unwrap()
+            .is_some_and(|retry_at| Instant::now() < retry_at)
+    }
+
+    fn insert(&self, key: CacheKey, translated: String) {
+        let mut cache = self.cache.lock().unwrap();
+        if cache.entries.insert(key.clone(), translated).is_none() {
+            cache.order.push_back(key);
+        }
+        while cache.order.len() > CACHE_CAPACITY {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Translates one session's captions from its source language into the guild's targets.
+pub struct SessionTranslator {
+    service: Arc<TranslationService>,
+    source: String,
+    targets: Vec<String>,
+}
+
+impl SessionTranslator {
+    pub fn new(service: Arc<TranslationService>, source: String, targets: Vec<String>) -> Self {
+        Self {
+            service,
+            source,
+            targets,
+        }
+    }
+
+    pub async fn translate(&self, text: &str) -> Vec<Translation> {
+        self.service
+            .translate_all(text, &self.source, &self.targets)
+            .await
+    }
+}
+
+pub struct TranslationServiceKey;
+impl TypeMapKey for TranslationServiceKey {
+    type Value = Arc<TranslationService>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    /// A stand-in LibreTranslate server that answers each request on its own thread after
+    /// `delay`, failing the first `failures` requests.
+    fn serve(delay: Duration, failures: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let index = counter.fetch_add(1, Ordering::SeqCst);
+                thread::spawn(move || respond(stream.unwrap(), delay, index < failures));
+            }
+        });
+        (url, requests)
+    }
+
+    fn respond(stream: TcpStream, delay: Duration, fail: bool) {
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        let request: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        thread::sleep(delay);
+        let (status, body) = if fail {
+            (
+                "500 Internal Server Error",
+                serde_json::json!({ "error": "overloaded" }),
+            )
+        } else {
+            let translated = format!(
+                "{}: {}",
+                request["target"].as_str().unwrap(),
+                request["q"].as_str().unwrap()
+            );
+            (
+                "200 OK",
+                serde_json::json!({ "translatedText": translated }),
+            )
+        };
+        let body = body.to_string();
+        let response = format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            body.len(),
+            body
+        );
+        reader.get_mut().write_all(response.as_bytes()).unwrap();
+    }
+
+    fn targets(languages: &[&str]) -> Vec<String> {
+        languages
+            .iter()
+            .map(|language| language.to_string())
+            .collect()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn translates_targets_concurrently() {
+        let delay = Duration::from_millis(400);
+        let (url, requests) = serve(delay, 0);
+        let service = TranslationService::new(Box::new(LibreTranslate::new(&url, None)));
+
+        let started = Instant::now();
+        let translations = service
+            .translate_all("hello", "en", &targets(&["de", "en", "es", "fr"]))
+            .await;
+
+        assert!(
+            started.elapsed() < delay * 2,
+            "took {:?}",
+            started.elapsed()
+        );
+        let languages: Vec<&str> = translations.iter().map(|t| t.language.as_str()).collect();
+        assert_eq!(languages, ["de", "es", "fr"]);
+        assert_eq!(translations[1].text, "es: hello");
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+
+        // Served from the cache
+        service
+            .translate_all("hello", "en", &targets(&["de"]))
+            .await;
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn backs_off_after_a_failure() {
+        let (url, requests) = serve(Duration::ZERO, 1);
+        let service = TranslationService::new(Box::new(LibreTranslate::new(&url, None)));
+
+        assert!(service
+            .translate_all("hi", "en", &targets(&["de"]))
+            .await
+            .is_empty());
+        assert!(service
+            .translate_all("hi", "en", &targets(&["de"]))
+            .await
+            .is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,328 @@
+use serenity::model::id::{ChannelId, UserId};
+use songbird::input::{Input, RawAdapter};
+use songbird::Call;
+use std::io::Cursor;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::{mpsc, Mutex};
+use tracing::{debug, warn, Instrument, Span};
+
+const SYNTHESIS_TIMEOUT: Duration = Duration::from_secs(15);
+/// Messages waiting to be synthesized or played; later messages are dropped.
+const MAX_QUEUED: usize = 8;
+/// Longer messages are cut off, so one wall of text can't hold the channel.
+const MAX_CHARS: usize = 300;
+const MAX_VOICE_LEN: usize = 64;
+
+/// A message to read aloud.
+pub struct Utterance {
+    pub author: UserId,
+    pub author_name: String,
+    pub voice: String,
+    pub text: String,
+}
+
+/// Reads a linked text channel into the session's voice channel.
+///
+/// Messages are synthesized one at a time by a local TTS command and queued on the session's
+/// songbird `Call`, so they play in order without overlapping.
+#[derive(Clone)]
+pub struct TtsReader {
+    channel_id: ChannelId,
+    queue: mpsc::Sender<Utterance>,
+}
+
+impl TtsReader {
+    /// `command` gets the text on stdin and must write a 16-bit PCM WAV file to stdout;
+    /// `{voice}` in its arguments is replaced with the speaker's voice.
+    pub fn spawn(
+        command: Vec<String>,
+        call: Arc<Mutex<Call>>,
+        channel_id: ChannelId,
+        span: Span,
+    ) -> Self {
+        let (queue, utterances) = mpsc::channel(MAX_QUEUED);
+        tokio::spawn(read_aloud(command, call, utterances).instrument(span));
+        Self { channel_id, queue }
+    }
+
+    pub fn channel_id(&self) -> ChannelId {
+        self.channel_id
+    }
+
+    /// Queues an utterance, returning false if the queue is full.
+    pub fn say(&self, utterance: Utterance) -> bool {
+        self.queue.try_send(utterance).is_ok()
+    }
+}
+
+async fn read_aloud(
+    command: Vec<String>,
+    call: Arc<Mutex<Call>>,
+    mut utterances: mpsc::Receiver<Utterance>,
+) {
+    let mut last_author = None;
+    while let Some(utterance) = utterances.recv().await {
+        let Some(text) = speakable(&utterance.text) else {
+            continue;
+        };
+        // Name the author when the speaker changes, like a caption label
+        let text = if last_author == Some(utterance.author) {
+            text
+        } else {
+            format!("{} says: {}", utterance.author_name, text)
+        };
+        last_author = Some(utterance.author);
+
+        if call.lock().await.queue().len() >= MAX_QUEUED {
+            warn!("Read-aloud queue is full, skipping message");
+            continue;
+        }
+        let input = match synthesize(&command, &utterance.voice, &text).await {
+            Ok(input) => input,
+            Err(e) => {
+                warn!(voice = %utterance.voice, "Text-to-speech failed: {}", e);
+                continue;
+            }
+        };
+        call.lock().await.enqueue_input(input).await;
+        debug!(chars = text.len(), "Queued message for read-aloud");
+    }
+    debug!("Read-aloud stopped");
+}
+
+async fn synthesize(command: &[String], voice: &str, text: &str) -> Result<Input, String> {
+    let (program, args) = command.split_first().ok_or("empty command")?;
+
+    let mut child = Command::new(program)
+        .args(args.iter().map(|arg| arg.replace("{voice}", voice)))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("failed to start {}: {}", program, e))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(text.as_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let output = tokio::time::timeout(SYNTHESIS_TIMEOUT, child.wait_with_output())
+        .await
+        .map_err(|_| String::from("timed out"))?
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        return Err(format!("exited with {}", output.status));
+    }
+
+    let pcm = decode_wav(&output.stdout)?;
+    Ok(RawAdapter::new(Cursor::new(pcm.samples), pcm.sample_rate, pcm.channels).into())
+}
+
+/// Interleaved little-endian f32 samples, as songbird's raw input expects.
+struct Pcm {
+    samples: Vec<u8>,
+    sample_rate: u32,
+    channels: u32,
+}
+
+/// Decodes a 16-bit PCM WAV file, as written by espeak-ng and piper.
+fn decode_wav(wav: &[u8]) -> Result<Pcm, String> {
+    let u16_at = |at: usize| u16::from_le_bytes([wav[at], wav[at + 1]]);
+    let u32_at = |at: usize| u32::from_le_bytes([wav[at], wav[at + 1], wav[at + 2], wav[at + 3]]);
+
+    if wav.len() < 12 || &wav[..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return Err(String::from("output is not a WAV file"));
+    }
+
+    let mut format = None;
+    let mut pos = 12;
+    while pos + 8 <= wav.len() {
+        let size = u32_at(pos + 4) as usize;
+        let body = pos + 8;
+        match &wav[pos..pos + 4] {
+            b"fmt " if body + 16 <= wav.len() => {
+                let (tag, channels, sample_rate, bits) = (
+                    u16_at(body),
+                    u16_at(body + 2),
+                    u32_at(body + 4),
+                    u16_at(body + 14),
+                );
+                if tag != 1 || bits != 16 {
+                    return Err(format!(
+                        "unsupported WAV encoding {} with {} bits per sample",
+                        tag, bits
+                    ));
+                }
+                format = Some((u32::from(channels), sample_rate));
+            }
+            b"data" => {
+                let (channels, sample_rate) = format.ok_or("WAV data before format")?;
+                // Output streamed to a pipe can't fill in the size, so read to the end
+                let end = body.saturating_add(size).min(wav.len());
+                let samples = wav[body..end]
+                    .chunks_exact(2)
+                    .flat_map(|sample| {
+                        (f32::from(i16::from_le_bytes([sample[0], sample[1]])) / 32768.0)
+                            .to_le_bytes()
+                    })
+                    .collect();
+                return Ok(Pcm {
+                    samples,
+                    sample_rate,
+                    channels,
+                });
+            }
+            _ => {}
+        }
+        // Chunks are padded to an even length
+        pos = body.saturating_add(size).saturating_add(size & 1);
+    }
+    Err(String::from("WAV file has no audio data"))
+}
+
+/// Prepares chat text for speech: links and custom emoji are shortened, markdown is dropped
+/// and long messages are cut off. Returns `None` if nothing is left to say.
+fn speakable(text: &str) -> Option<String> {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|word| {
+            if word.starts_with("http://") || word.starts_with("https://") {
+                String::from("link")
+            } else if let Some(name) = custom_emoji_name(word) {
+                name.to_string()
+            } else {
+                word.chars()
+                    .filter(|c| !matches!(c, '*' | '_' | '~' | '|' | '`' | '>'))
+                    .collect()
+            }
+        })
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let text = words.join(" ");
+    Some(match text.char_indices().nth(MAX_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text,
+    })
+}
+
+/// `<:name:id>` or `<a:name:id>` to `name`.
+fn custom_emoji_name(word: &str) -> Option<&str> {
+    let inner = word.strip_prefix('<')?.strip_suffix('>')?;
+    let inner = inner.strip_prefix('a').unwrap_or(inner);
+    let (name, id) = inner.strip_prefix(':')?.split_once(':')?;
+    id.chars().all(|c| c.is_ascii_digit()).then_some(name)
+}
+
+/// Voice names are substituted into the TTS command, so they're limited to characters that
+/// can't change its meaning, e.g. `en-us`, `en+f3` or `de_DE-thorsten-medium`.
+pub fn is_valid_voice(voice: &str) -> bool {
+    !voice.is_empty()
+        && voice.len() <= MAX_VOICE_LEN
+        && voice
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '.'))
+        && !voice.starts_with(['-', '.'])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend((body.len() as u32).to_le_bytes());
+        chunk.extend(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn fmt(tag: u16, channels: u16, sample_rate: u32, bits: u16) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend(tag.to_le_bytes());
+        body.extend(channels.to_le_bytes());
+        body.extend(sample_rate.to_le_bytes());
+        body.extend((sample_rate * u32::from(channels * bits / 8)).to_le_bytes());
+        body.extend((channels * bits / 8).to_le_bytes());
+        body.extend(bits.to_le_bytes());
+        chunk(b"fmt ", &body)
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut wav = b"RIFF".to_vec();
+        wav.extend((body.len() as u32 + 4).to_le_bytes());
+        wav.extend(b"WAVE");
+        wav.extend(body);
+        wav
+    }
+
+    fn pcm16(samples: &[i16]) -> Vec<u8> {
+        samples
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes())
+            .collect()
+    }
+
+    fn floats(pcm: &Pcm) -> Vec<f32> {
+        pcm.samples
+            .chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()
+    }
+
+    #[test]
+    fn decodes_16_bit_pcm() {
+        let pcm = decode_wav(&wav(&[
+            fmt(1, 1, 22_050, 16),
+            chunk(b"data", &pcm16(&[0, 16_384, -32_768])),
+        ]))
+        .unwrap();
+        assert_eq!((pcm.sample_rate, pcm.channels), (22_050, 1));
+        assert_eq!(floats(&pcm), [0.0, 0.5, -1.0]);
+    }
+
+    #[test]
+    fn skips_padded_chunks() {
+        let pcm = decode_wav(&wav(&[
+            chunk(b"LIST", b"odd"),
+            fmt(1, 2, 16_000, 16),
+            chunk(b"data", &pcm16(&[16_384, -16_384])),
+        ]))
+        .unwrap();
+        assert_eq!((pcm.sample_rate, pcm.channels), (16_000, 2));
+        assert_eq!(floats(&pcm), [0.5, -0.5]);
+    }
+
+    #[test]
+    fn reads_streamed_data_to_the_end() {
+        // Written to a pipe, so the data size is left at its placeholder
+        let mut data = b"data".to_vec();
+        data.extend(u32::MAX.to_le_bytes());
+        data.extend(pcm16(&[16_384, 16_384, 16_384]));
+        let pcm = decode_wav(&wav(&[fmt(1, 1, 22_050, 16), data])).unwrap();
+        assert_eq!(floats(&pcm), [0.5; 3]);
+    }
+
+    #[test]
+    fn rejects_unsupported_files() {
+        assert!(decode_wav(b"not a wav file").is_err());
+        assert!(decode_wav(&wav(&[fmt(1, 1, 22_050, 8), chunk(b"data", &[0; 4])])).is_err());
+        assert!(decode_wav(&wav(&[fmt(3, 1, 22_050, 32), chunk(b"data", &[0; 4])])).is_err());
+        assert!(decode_wav(&wav(&[chunk(b"data", &[0; 4]), fmt(1, 1, 22_050, 16)])).is_err());
+        assert!(decode_wav(&wav(&[fmt(1, 1, 22_050, 16)])).is_err());
+    }
+}
@@ -1,18 +1,299 @@
 use std::error::Error;
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use vosk::Model;
 
-const MODEL_NAME: &str = "vosk-model-en-us-0.22";
+pub const DEFAULT_MODEL_NAME: &str = "vosk-model-en-us-0.22";
+const MODEL_BASE_URL: &str = "https://alphacephei.com/vosk/models";
 
-pub fn load() -> Result<Arc<Model>, Box<dyn Error>> {
-    let path = PathBuf::from(format!("models/{}", MODEL_NAME));
+// Set by build.rs to the model's location in the asset cache
+const DEFAULT_MODEL_PATH: &str = env!("VOSK_DEFAULT_MODEL_PATH");
 
+/// A required model file that is missing, with a hint on how to fix it.
+pub struct Problem {
+    pub file: &'static str,
+    pub hint: &'static str,
+}
+
+const REQUIRED_FILES: &[Problem] = &[
+    Problem {
+        file: "am/final.mdl",
+        hint: "acoustic model; the archive was probably truncated, reinstall the model",
+    },
+    Problem {
+        file: "conf/model.conf",
+        hint: "decoder configuration; check the path points at the model root, not a subdirectory",
+    },
+    Problem {
+        file: "ivector/final.ie",
+        hint: "speaker adaptation extractor; reinstall the model or use a complete release",
+    },
+];
+
+const GRAPH_PROBLEM: Problem = Problem {
+    file: "graph/HCLG.fst (or graph/HCLr.fst + graph/Gr.fst)",
+    hint: "decoding graph; the model is incomplete, reinstall it",
+};
+
+/// Directory models are installed into when none is configured.
+pub fn default_model_dir() -> PathBuf {
+    Path::new(DEFAULT_MODEL_PATH)
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("models"))
+}
+
+/// Resolves a model given by name (inside `model_dir`) or by path.
+pub fn resolve(model_dir: &Path, model: &str) -> PathBuf {
+    let as_path = Path::new(model);
+    if as_path.components().count() > 1 || as_path.is_dir() {
+        as_path.to_path_buf()
+    } else {
+        model_dir.join(model)
+    }
+}
+
+/// Language code from a Vosk model name, e.g. "en" for `vosk-model-small-en-us-0.15`.
+pub fn model_language(name: &str) -> Option<String> {
+    name.split('-')
+        .filter(|part| !matches!(*part, "vosk" | "model" | "small"))
+        .find(|part| (2..=3).contains(&part.len()) && part.chars().all(|c| c.is_ascii_lowercase()))
+        .map(String::from)
+}
+
+pub fn validate(path: &Path) -> Vec<&'static Problem> {
+    let mut problems: Vec<&'static Problem> = REQUIRED_FILES
+        .iter()
+        .filter(|problem| !path.join(problem.file).is_file())
+        .collect();
+
+    // Models ship either a static HCLG graph or a dynamic HCLr + Gr pair
+    let graph = path.join("graph");
+    let static_graph = graph.join("HCLG.fst").is_file();
+    let dynamic_graph = graph.join("HCLr.fst").is_file() && graph.join("Gr.fst").is_file();
+    if !static_graph && !dynamic_graph {
+        problems.push(&GRAPH_PROBLEM);
+    }
+
+    problems
+}
+
+fn describe(path: &Path, problems: &[&Problem]) -> String {
+    let mut message = format!("Model at {} is not usable:", path.display());
+    for problem in problems {
+        message.push_str(&format!("\n  missing {} ({})", problem.file, problem.hint));
+    }
+    message
+}
+
+pub fn load(path: &Path) -> Result<Arc<Model>, Box<dyn Error>> {
     if !path.is_dir() {
-        return Err(format!("Model not found: {}", path.display()).into());
+        return Err(format!(
+            "Model not found: {} (install it with `models install <name>`)",
+            path.display()
+        )
+        .into());
+    }
+
+    let problems = validate(path);
+    if !problems.is_empty() {
+        return Err(describe(path, &problems).into());
     }
 
     Model::new(path.to_string_lossy().as_ref())
         .map(Arc::new)
         .ok_or_else(|| format!("Failed to load model from {}", path.display()).into())
 }
+
+pub fn list(model_dir: &Path) -> Result<(), Box<dyn Error>> {
+    let entries = match fs::read_dir(model_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!("No models installed in {}", model_dir.display());
+            return Ok(());
+        }
+        Err(e) => return Err(format!("{}: {}", model_dir.display(), e).into()),
+    };
+
+    let mut names: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| !name.starts_with('.') && !name.ends_with(".partial"))
+        .collect();
+    names.sort();
+
+    if names.is_empty() {
+        println!("No models installed in {}", model_dir.display());
+    }
+
+    for name in names {
+        let problems = validate(&model_dir.join(&name));
+        let default = if name == DEFAULT_MODEL_NAME {
+            " (default)"
+        } else {
+            ""
+        };
+        if problems.is_empty() {
+            println!("{}{}", name, default);
+        } else {
+            println!(
+                "{}{} — incomplete, run `models verify {}`",
+                name, default, name
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub fn verify(model_dir: &Path, model: &str) -> Result<(), Box<dyn Error>> {
+    let path = resolve(model_dir, model);
+    if !path.is_dir() {
+        return Err(format!("Model not found: {}", path.display()).into());
+    }
+
+    let problems = validate(&path);
+    if !problems.is_empty() {
+        return Err(describe(&path, &problems).into());
+    }
+
+    println!("{} is complete", path.display());
+    Ok(())
+}
+
+pub fn remove(model_dir: &Path, name: &str) -> Result<(), Box<dyn Error>> {
+    if Path::new(name).components().count() != 1 || name.starts_with('.') {
+        return Err(format!("Invalid model name: {}", name).into());
+    }
+
+    let path = model_dir.join(name);
+    if !path.is_dir() {
+        return Err(format!("Model not found: {}", path.display()).into());
+    }
+
+    fs::remove_dir_all(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    println!("Removed {}", path.display());
+    Ok(())
+}
+
+/// Installs a model from a local zip archive or by name from the Vosk model repository.
+pub fn install(model_dir: &Path, source: &str) -> Result<(), Box<dyn Error>> {
+    fs::create_dir_all(model_dir).map_err(|e| format!("{}: {}", model_dir.display(), e))?;
+
+    let source_path = Path::new(source);
+    let (archive, downloaded) = if source_path.is_file() {
+        (source_path.to_path_buf(), false)
+    } else if source.ends_with(".zip") || source_path.components().count() != 1 {
+        return Err(format!("Archive not found: {}", source).into());
+    } else {
+        let archive = model_dir.join(format!(".{}.zip", source));
+        let url = format!("{}/{}.zip", MODEL_BASE_URL, source);
+        println!("Downloading {}...", url);
+        download(&url, &archive)?;
+        (archive, true)
+    };
+
+    let result = extract_model(&archive, model_dir);
+    if downloaded {
+        let _ = fs::remove_file(&archive);
+    }
+
+    let path = result?;
+    println!("Installed {}", path.display());
+    Ok(())
+}
+
+fn download(url: &str, dest: &Path) -> Result<(), Box<dyn Error>> {
+    let response = ureq::get(url)
+        .timeout(std::time::Duration::from_secs(600))
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::Status(404, _) => {
+                format!("No model at {}; see {} for names", url, MODEL_BASE_URL)
+            }
+            e => format!("Download failed: {}", e),
+        })?;
+
+    let mut reader = response.into_reader();
+    let mut file = fs::File::create(dest)?;
+    let mut buffer = [0u8; 65536];
+
+    loop {
+        let bytes_read = reader.read(&mut buffer)?;
+        if bytes_read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..bytes_read])?;
+    }
+
+    Ok(())
+}
+
+/// Extracts to a staging directory and only moves the model into place once it validates.
+fn extract_model(archive: &Path, model_dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+    let stem = archive
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().trim_start_matches('.').to_string())
+        .ok_or("Invalid archive name")?;
+    let staging = model_dir.join(format!(".{}.partial", stem));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    let file = fs::File::open(archive).map_err(|e| format!("{}: {}", archive.display(), e))?;
+    zip::ZipArchive::new(file)
+        .and_then(|mut zip| zip.extract(&staging))
+        .map_err(|e| format!("Failed to extract {}: {}", archive.display(), e))?;
+
+    // Archives either contain the model files directly or a single top-level directory
+    let (root, name) = match single_subdirectory(&staging)? {
+        Some(dir) => {
+            let name = dir
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            (dir, name)
+        }
+        None => (staging.clone(), stem),
+    };
+
+    let problems = validate(&root);
+    if !problems.is_empty() {
+        let message = describe(&root, &problems);
+        fs::remove_dir_all(&staging)?;
+        return Err(message.into());
+    }
+
+    let dest = model_dir.join(&name);
+    if dest.exists() {
+        fs::remove_dir_all(&staging)?;
+        return Err(format!(
+            "{} is already installed, remove it first with `models remove {}`",
+            name, name
+        )
+        .into());
+    }
+
+    fs::rename(&root, &dest)?;
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    Ok(dest)
+}
+
+fn single_subdirectory(dir: &Path) -> io::Result<Option<PathBuf>> {
+    let entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect();
+
+    match entries.as_slice() {
+        [only] if only.is_dir() => Ok(Some(only.clone())),
+        _ => Ok(None),
+    }
+}